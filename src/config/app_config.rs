@@ -0,0 +1,337 @@
+use std::{collections::HashMap, env, fs, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::handle::base_token::{USDC, USDT};
+use crate::price::pyth;
+use solana_sdk::{commitment_config::{CommitmentConfig, CommitmentLevel}, pubkey::Pubkey};
+
+// 配置文件路径，可通过该环境变量覆盖
+pub const CONFIG_PATH_ENV: &str = "JUPITER_CONFIG";
+pub const DEFAULT_CONFIG_PATH: &str = "config.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    pub listen_addr: String,
+    pub rpc: RpcConfig,
+    pub price: PriceConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RpcConfig {
+    pub urls: Vec<String>,
+    pub weights: Vec<u32>,// 与 urls 一一对应的负载均衡权重，缺省为 1
+    pub unhealthy_threshold: u32,// 连续失败多少次后将端点标记为不健康
+    pub cooldown_secs: u64,// 不健康端点被摘除的时长
+    pub commitment: String,// processed / confirmed / finalized
+    pub max_signatures: Option<usize>,// 请求未指定 maxSignatures 时的默认回溯深度，None 表示遍历完整历史
+    pub concurrency: usize,// 同时获取交易的任务数
+    pub retries: u32,// 可重试错误（超时、429、5xx、节点不健康等）在所有端点都失败一轮后的最大退避重试次数，故障转移不计入
+    pub requests_per_second: f64,// 令牌桶每秒补充的请求数
+    pub burst: u32,// 令牌桶容量
+    pub backoff_base_ms: u64,// 指数退避的初始间隔
+    pub backoff_max_ms: u64,// 指数退避的最大间隔
+    pub timeout_secs: u64,// 单次 HTTP 请求超时
+    pub fetch_mode: FetchMode,
+    pub batch_size: usize,// batch 模式下每个 HTTP 请求携带的 getTransaction 数量
+}
+
+// 交易获取方式：single 为每个签名一次 getTransaction，batch 为 JSON-RPC batch 请求
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchMode {
+    Single,
+    Batch,
+}
+
+impl FromStr for FetchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "single" => Ok(FetchMode::Single),
+            "batch" => Ok(FetchMode::Batch),
+            _ => Err(format!("Invalid fetch mode: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PriceConfig {
+    pub pyth_base_url: String,
+    pub jupiter_base_url: String,
+    pub retries: u32,
+    pub pyth_feeds: HashMap<String, String>,// mint -> Pyth price feed id，补充或覆盖内置的映射
+    pub implied_price_max_distance_secs: u64,// 使用相邻交易的隐含价格时允许的最大时间差
+    pub pyth_max_staleness_secs: u64,// Pyth 历史价格的发布时间与交易时间允许的最大差值
+    pub stablecoins: Vec<String>,// 按自身 Pyth feed 检查锚定的稳定币 mint，feed 映射见 pyth_feeds
+    pub peg_tolerance: f64,// 稳定币价格与 1 USD 的相对偏差在该范围内时按 1 USD 计，0 表示总是使用 feed 价格
+    pub peg_check_interval_secs: u64,// 确认锚定后，同一时间段内不再请求 feed
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen_addr: "0.0.0.0:80".to_string(),
+            rpc: RpcConfig::default(),
+            price: PriceConfig::default(),
+        }
+    }
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            urls: vec!["https://api.mainnet-beta.solana.com".to_string()],
+            weights: vec![],
+            unhealthy_threshold: 3,
+            cooldown_secs: 30,
+            commitment: "finalized".to_string(),
+            max_signatures: None,
+            concurrency: 25,
+            retries: 5,
+            requests_per_second: 10.0,
+            burst: 10,
+            backoff_base_ms: 200,
+            backoff_max_ms: 10_000,
+            timeout_secs: 30,
+            fetch_mode: FetchMode::Single,
+            batch_size: 20,
+        }
+    }
+}
+
+impl Default for PriceConfig {
+    fn default() -> Self {
+        PriceConfig {
+            pyth_base_url: "https://hermes.pyth.network".to_string(),
+            jupiter_base_url: "https://lite-api.jup.ag".to_string(),
+            retries: 3,
+            pyth_feeds: HashMap::new(),
+            implied_price_max_distance_secs: 7 * 24 * 60 * 60,
+            pyth_max_staleness_secs: 60,
+            stablecoins: vec![USDC.to_string(), USDT.to_string()],
+            peg_tolerance: 0.005,
+            peg_check_interval_secs: 600,
+        }
+    }
+}
+
+impl Config {
+    // 先读取配置文件（不存在则使用默认值），再用环境变量覆盖
+    pub fn load() -> Result<Self, String> {
+        let path = env::var(CONFIG_PATH_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+        let mut config = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<Config>(&content)
+                .map_err(|e| format!("Invalid config file {}: {}", path, e))?,
+            Err(_) => {
+                println!("Config file {} not found, using defaults", path);
+                Config::default()
+            }
+        };
+        config.apply_env(|key| env::var(key).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self, get: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(addr) = get("JUPITER_LISTEN_ADDR") {
+            self.listen_addr = addr;
+        }
+        // 多个 RPC 以逗号分隔
+        if let Some(urls) = get("JUPITER_RPC_URLS") {
+            self.rpc.urls = urls
+                .split(',')
+                .map(|url| url.trim().to_string())
+                .filter(|url| !url.is_empty())
+                .collect();
+        }
+        if let Some(weights) = get("JUPITER_RPC_WEIGHTS") {
+            self.rpc.weights = weights
+                .split(',')
+                .map(|weight| parse_env("JUPITER_RPC_WEIGHTS", weight))
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(commitment) = get("JUPITER_RPC_COMMITMENT") {
+            self.rpc.commitment = commitment;
+        }
+        if let Some(max_signatures) = get("JUPITER_RPC_MAX_SIGNATURES") {
+            self.rpc.max_signatures = Some(parse_env("JUPITER_RPC_MAX_SIGNATURES", &max_signatures)?);
+        }
+        if let Some(concurrency) = get("JUPITER_RPC_CONCURRENCY") {
+            self.rpc.concurrency = parse_env("JUPITER_RPC_CONCURRENCY", &concurrency)?;
+        }
+        if let Some(retries) = get("JUPITER_RPC_RETRIES") {
+            self.rpc.retries = parse_env("JUPITER_RPC_RETRIES", &retries)?;
+        }
+        if let Some(rate) = get("JUPITER_RPC_REQUESTS_PER_SECOND") {
+            self.rpc.requests_per_second = parse_env("JUPITER_RPC_REQUESTS_PER_SECOND", &rate)?;
+        }
+        if let Some(burst) = get("JUPITER_RPC_BURST") {
+            self.rpc.burst = parse_env("JUPITER_RPC_BURST", &burst)?;
+        }
+        if let Some(backoff_base_ms) = get("JUPITER_RPC_BACKOFF_BASE_MS") {
+            self.rpc.backoff_base_ms = parse_env("JUPITER_RPC_BACKOFF_BASE_MS", &backoff_base_ms)?;
+        }
+        if let Some(backoff_max_ms) = get("JUPITER_RPC_BACKOFF_MAX_MS") {
+            self.rpc.backoff_max_ms = parse_env("JUPITER_RPC_BACKOFF_MAX_MS", &backoff_max_ms)?;
+        }
+        if let Some(timeout_secs) = get("JUPITER_RPC_TIMEOUT_SECS") {
+            self.rpc.timeout_secs = parse_env("JUPITER_RPC_TIMEOUT_SECS", &timeout_secs)?;
+        }
+        if let Some(threshold) = get("JUPITER_RPC_UNHEALTHY_THRESHOLD") {
+            self.rpc.unhealthy_threshold = parse_env("JUPITER_RPC_UNHEALTHY_THRESHOLD", &threshold)?;
+        }
+        if let Some(cooldown_secs) = get("JUPITER_RPC_COOLDOWN_SECS") {
+            self.rpc.cooldown_secs = parse_env("JUPITER_RPC_COOLDOWN_SECS", &cooldown_secs)?;
+        }
+        if let Some(fetch_mode) = get("JUPITER_RPC_FETCH_MODE") {
+            self.rpc.fetch_mode = fetch_mode.parse()?;
+        }
+        if let Some(batch_size) = get("JUPITER_RPC_BATCH_SIZE") {
+            self.rpc.batch_size = parse_env("JUPITER_RPC_BATCH_SIZE", &batch_size)?;
+        }
+        if let Some(url) = get("JUPITER_PYTH_BASE_URL") {
+            self.price.pyth_base_url = url;
+        }
+        if let Some(url) = get("JUPITER_PRICE_BASE_URL") {
+            self.price.jupiter_base_url = url;
+        }
+        if let Some(retries) = get("JUPITER_PRICE_RETRIES") {
+            self.price.retries = parse_env("JUPITER_PRICE_RETRIES", &retries)?;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.rpc.urls.is_empty() {
+            return Err("At least one RPC url is required".to_string());
+        }
+        // 权重缺省时全部为 1；指定时必须与 urls 一一对应，避免配置错误悄悄改变流量分配
+        if !self.rpc.weights.is_empty() && self.rpc.weights.len() != self.rpc.urls.len() {
+            return Err(format!("RPC weights must match urls: {} weights for {} urls", self.rpc.weights.len(), self.rpc.urls.len()));
+        }
+        if self.rpc.weights.contains(&0) {
+            return Err("RPC weights must be greater than 0".to_string());
+        }
+        if self.rpc.concurrency == 0 {
+            return Err("RPC concurrency must be greater than 0".to_string());
+        }
+        if self.rpc.requests_per_second <= 0.0 {
+            return Err("RPC requests per second must be greater than 0".to_string());
+        }
+        if self.rpc.batch_size == 0 {
+            return Err("RPC batch size must be greater than 0".to_string());
+        }
+        self.rpc.commitment_config()?;
+        for mint in &self.price.stablecoins {
+            Pubkey::from_str(mint).map_err(|_| format!("Invalid stablecoin mint: {}", mint))?;
+            // 稳定币的锚定检查依赖其 Pyth feed
+            if !pyth::has_builtin_feed(mint) && !self.price.pyth_feeds.contains_key(mint) {
+                return Err(format!("No pyth feed for stablecoin {}, add it to pythFeeds", mint));
+            }
+        }
+        if !(0.0..1.0).contains(&self.price.peg_tolerance) {
+            return Err(format!("Peg tolerance must be in [0, 1): {}", self.price.peg_tolerance));
+        }
+        for (mint, feed_id) in &self.price.pyth_feeds {
+            Pubkey::from_str(mint).map_err(|_| format!("Invalid mint in pyth feeds: {}", mint))?;
+            let hex = feed_id.strip_prefix("0x").unwrap_or(feed_id);
+            if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("Invalid pyth feed id for {}: {}", mint, feed_id));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RpcConfig {
+    pub fn commitment_config(&self) -> Result<CommitmentConfig, String> {
+        CommitmentLevel::from_str(&self.commitment)
+            .map(|commitment| CommitmentConfig { commitment })
+            .map_err(|_| format!("Invalid commitment: {}", self.commitment))
+    }
+}
+
+fn parse_env<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse::<T>()
+        .map_err(|_| format!("Invalid value for {}: {}", key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_file_with_env_overrides() {
+        let mut config = serde_json::from_str::<Config>(r#"{
+            "listenAddr": "127.0.0.1:8080",
+            "rpc": { "urls": ["https://a.example"], "concurrency": 8 }
+        }"#).unwrap();
+        // 未出现在文件中的字段使用默认值
+        assert_eq!(config.rpc.retries, 5);
+        assert_eq!(config.price.retries, 3);
+
+        let vars = HashMap::from([
+            ("JUPITER_RPC_URLS", "https://b.example, https://c.example"),
+            ("JUPITER_RPC_WEIGHTS", "3,1"),
+            ("JUPITER_RPC_COMMITMENT", "confirmed"),
+            ("JUPITER_RPC_MAX_SIGNATURES", "500"),
+            ("JUPITER_RPC_FETCH_MODE", "Batch"),
+            ("JUPITER_RPC_BACKOFF_BASE_MS", "100"),
+            ("JUPITER_RPC_BACKOFF_MAX_MS", "2000"),
+            ("JUPITER_RPC_TIMEOUT_SECS", "10"),
+            ("JUPITER_RPC_UNHEALTHY_THRESHOLD", "5"),
+            ("JUPITER_RPC_COOLDOWN_SECS", "120"),
+        ]);
+        config.apply_env(|key| vars.get(key).map(|v| v.to_string())).unwrap();
+        config.validate().unwrap();
+
+        assert_eq!(config.listen_addr, "127.0.0.1:8080");
+        assert_eq!(config.rpc.urls, vec!["https://b.example", "https://c.example"]);
+        assert_eq!(config.rpc.weights, vec![3, 1]);
+        assert_eq!(config.rpc.concurrency, 8);
+        assert_eq!(config.rpc.max_signatures, Some(500));
+        assert_eq!(config.rpc.fetch_mode, FetchMode::Batch);
+        assert_eq!((config.rpc.backoff_base_ms, config.rpc.backoff_max_ms, config.rpc.timeout_secs), (100, 2_000, 10));
+        assert_eq!((config.rpc.unhealthy_threshold, config.rpc.cooldown_secs), (5, 120));
+        assert_eq!(config.rpc.commitment_config().unwrap(), CommitmentConfig::confirmed());
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let mut config = Config::default();
+        assert!(config.apply_env(|key| (key == "JUPITER_RPC_CONCURRENCY").then(|| "many".to_string())).is_err());
+
+        config.rpc.commitment = "instant".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.price.pyth_feeds.insert("JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN".to_string(), "0x1234".to_string());
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.rpc.urls = vec!["https://a.example".to_string(), "https://b.example".to_string()];
+        config.rpc.weights = vec![3];
+        assert!(config.validate().is_err());
+        config.rpc.weights = vec![3, 0];
+        assert!(config.validate().is_err());
+        config.rpc.weights = vec![3, 1];
+        assert!(config.validate().is_ok());
+
+        // 稳定币必须有内置或配置的 Pyth feed
+        let mut config = Config::default();
+        assert!(config.validate().is_ok());
+        let pyusd = "2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo".to_string();
+        config.price.stablecoins.push(pyusd.clone());
+        assert!(config.validate().is_err());
+        config.price.pyth_feeds.insert(pyusd, "0xc1da1b73d7f01e7ddd54b3766cf7fcd644395ad14f70aa706ec5384c59e76692".to_string());
+        assert!(config.validate().is_ok());
+    }
+}
@@ -0,0 +1,106 @@
+use axum::{http::StatusCode, response::{IntoResponse, Response}, Json};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PriceError {
+    #[error("No price source for mint {0}")]
+    UnsupportedMint(String),
+    #[error("Price request failed: {0}")]
+    Request(String),
+    #[error("Price api responded with status {0}")]
+    Status(u16),
+    #[error("Invalid price response: {0}")]
+    InvalidResponse(String),
+    #[error("Price not found for mint {0}")]
+    NotFound(String),
+    #[error("Price for mint {0} was published {1}s away from the requested time")]
+    Stale(String, u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum IndexerError {
+    #[error("Invalid pubkey: {0}")]
+    InvalidPubkey(String),
+    #[error("RPC error: {0}")]
+    Rpc(String),
+    #[error("No transactions found for {0}")]
+    NoTransactions(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PnlError {
+    #[error("No swaps found for mint {0}")]
+    NoSwaps(String),
+    #[error(transparent)]
+    Price(#[from] PriceError),
+}
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum AppError {
+    #[error(transparent)]
+    Indexer(#[from] IndexerError),
+    #[error(transparent)]
+    Price(#[from] PriceError),
+    #[error(transparent)]
+    Pnl(#[from] PnlError),
+}
+
+// /pnl 失败时返回的 JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+}
+
+impl AppError {
+    // 机器可读的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Indexer(IndexerError::InvalidPubkey(_)) => "INVALID_PUBKEY",
+            AppError::Indexer(IndexerError::Rpc(_)) => "RPC_ERROR",
+            AppError::Indexer(IndexerError::NoTransactions(_)) => "NO_TRANSACTIONS",
+            AppError::Pnl(PnlError::NoSwaps(_)) => "NO_SWAPS",
+            AppError::Price(_) | AppError::Pnl(PnlError::Price(_)) => "PRICE_ERROR",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Indexer(IndexerError::InvalidPubkey(_)) => StatusCode::BAD_REQUEST,
+            AppError::Indexer(IndexerError::NoTransactions(_)) | AppError::Pnl(PnlError::NoSwaps(_)) => StatusCode::NOT_FOUND,
+            AppError::Indexer(IndexerError::Rpc(_)) | AppError::Price(_) | AppError::Pnl(PnlError::Price(_)) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            code: self.code().to_string(),
+            message: self.to_string(),
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_mapping() {
+        let err = AppError::from(IndexerError::InvalidPubkey("abc".to_string()));
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(err.code(), "INVALID_PUBKEY");
+
+        let err = AppError::from(PnlError::from(PriceError::Status(500)));
+        assert_eq!(err.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(err.code(), "PRICE_ERROR");
+
+        let err = AppError::from(PnlError::NoSwaps("mint".to_string()));
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+        assert_eq!(err.to_string(), "No swaps found for mint mint");
+    }
+}
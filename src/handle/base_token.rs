@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, ops::{Add, Mul}};
 
+use crate::error::app_error::PriceError;
+
 pub const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
 pub const USDT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
 pub const WSOL: &str = "So11111111111111111111111111111111111111112";
@@ -9,7 +11,10 @@ pub const PYTH_SOL_USD_PRICE_FEED_ID: &str = "0xef0d8b6fda2ceba41da15d4095d1da39
 
 #[derive(Serialize, Deserialize, Debug)]
 struct PythPrice {
-    price: String
+    price: String,
+    conf: String,
+    expo: i32,
+    publish_time: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -30,6 +35,37 @@ struct JupiterPriceData {
 
 type JupiterPriceResponse = HashMap<String, JupiterPriceData>;
 
+// Pyth 价格：实际价格为 price * 10^expo，conf 为同一精度下的置信区间（±）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PythPriceData {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+impl PythPriceData {
+    // 价格不为正数或指数超出范围时无法表示为 DecimalAmount
+    pub fn to_decimal_amount(&self) -> Result<DecimalAmount, PriceError> {
+        let price = u64::try_from(self.price)
+            .ok()
+            .filter(|price| *price > 0)
+            .ok_or_else(|| PriceError::InvalidResponse(format!("Non-positive pyth price {}", self.price)))?;
+        let invalid_expo = || PriceError::InvalidResponse(format!("Unsupported pyth exponent {}", self.expo));
+        if self.expo <= 0 {
+            let decimals = self.expo.checked_neg().and_then(|expo| u8::try_from(expo).ok()).ok_or_else(invalid_expo)?;
+            Ok(DecimalAmount::new(price, decimals))
+        } else {
+            let scale = 10u64.checked_pow(self.expo.unsigned_abs()).ok_or_else(invalid_expo)?;
+            Ok(DecimalAmount::new(price.checked_mul(scale).ok_or_else(invalid_expo)?, 0))
+        }
+    }
+
+    pub fn confidence(&self) -> f64 {
+        self.conf as f64 * 10f64.powi(self.expo)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct DecimalAmount {
     pub amount_in_int: u64,
@@ -44,6 +80,12 @@ impl DecimalAmount {
         }
     }
 
+    // 负数与非有限值按 0 计
+    pub fn from_float(value: f64, decimals: u8) -> Self {
+        let amount_in_int = (value * 10f64.powi(i32::from(decimals))).round();
+        DecimalAmount::new(if amount_in_int.is_finite() && amount_in_int > 0.0 { amount_in_int as u64 } else { 0 }, decimals)
+    }
+
     pub fn to_float(&self) -> f64 {
         self.amount_in_int as f64 / 10f64.powi(self.decimals as i32)
     }
@@ -74,111 +116,142 @@ pub async fn retry_get_jupiter_price(
     mint: &str,
     url: &str,
     times: u32,
-) -> Option<f64> {
-    let mut attempts = 0;
-    while attempts < times {
-        if let Some(res) = reqwest::Client::new()
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| eprintln!("Error fetching price: {}", e))
-        .ok() {
-            if res.status() == reqwest::StatusCode::OK {
-                let jupiter_price = res.json::<JupiterPriceResponse>().await;
-                #[cfg(test)]
-                println!("Jupiter price response: {:?}", jupiter_price);
-
-                match jupiter_price {
-                    Ok(price) => {
-                        if let Some(parsed_price) = price.get(mint) {
-                            return Some(parsed_price.usd_price);
-                        }
-                    },
-                    Err(_) => {
-                        return None
-                    }
-                }
-            } else {
-                return None
+) -> Result<f64, PriceError> {
+    retry_get_jupiter_prices(url, times)
+        .await?
+        .get(mint)
+        .copied()
+        .ok_or_else(|| PriceError::NotFound(mint.to_string()))
+}
+
+// Price API 的 ids 参数可以是逗号分隔的多个 mint，返回 mint -> USD 价格，没有价格的 mint 不在结果中
+pub async fn retry_get_jupiter_prices(
+    url: &str,
+    times: u32,
+) -> Result<HashMap<String, f64>, PriceError> {
+    let mut last_error = PriceError::NotFound(url.to_string());
+    for _ in 0..times {
+        let res = match reqwest::Client::new().get(url).send().await {
+            Ok(res) => res,
+            Err(e) => {
+                eprintln!("Error fetching price: {}", e);
+                last_error = PriceError::Request(e.to_string());
+                continue;
             }
+        };
+        if res.status() != reqwest::StatusCode::OK {
+            return Err(PriceError::Status(res.status().as_u16()));
         }
-        attempts += 1;
+
+        let jupiter_price = res.json::<JupiterPriceResponse>().await;
+        #[cfg(test)]
+        println!("Jupiter price response: {:?}", jupiter_price);
+
+        return match jupiter_price {
+            Ok(prices) => Ok(prices.into_iter().map(|(mint, price)| (mint, price.usd_price)).collect()),
+            Err(e) => Err(PriceError::InvalidResponse(e.to_string())),
+        };
     }
-    None
+    Err(last_error)
 }
 
 pub async fn retry(
     url: &str,
     times: u32,
-) -> Option<DecimalAmount> {
-    let mut attempts = 0;
-    while attempts < times {
-        if let Some(res) = reqwest::Client::new()
-        .get(url)
-        .send()
-        .await
-        .map_err(|e| eprintln!("Error fetching price: {}", e))
-        .ok() {
-            if res.status() == reqwest::StatusCode::OK {
-                let pyth_price = res.json::<PythParsedPrice>().await;
-                match pyth_price {
-                    Ok(price) => {
-                        if let Some(parsed_price) = price.parsed.first() {
-                            if let Ok(price_in_int) = parsed_price.price.price.parse::<u64>() {
-                                return Some(DecimalAmount::new(price_in_int, 8)); // Assuming 6 decimals for Pyth prices
-                            }
-                        }
-                    },
-                    Err(_) => {
-                        return None
-                    }
-                }
-            } else {
-                return None
+) -> Result<PythPriceData, PriceError> {
+    let mut last_error = PriceError::NotFound(url.to_string());
+    for _ in 0..times {
+        let res = match reqwest::Client::new().get(url).send().await {
+            Ok(res) => res,
+            Err(e) => {
+                eprintln!("Error fetching price: {}", e);
+                last_error = PriceError::Request(e.to_string());
+                continue;
             }
+        };
+        if res.status() != reqwest::StatusCode::OK {
+            return Err(PriceError::Status(res.status().as_u16()));
         }
-        attempts += 1;
-    }
-    None
-}
 
-pub async fn get_price(
-    mint: &str,
-    timestamp: u64,
-) -> Option<DecimalAmount> {
-    
-    #[cfg(test)]
-    println!("Fetching price for mint: {}", mint);
-
-    match mint {
-        USDC => {
-            Some(DecimalAmount::new(1_000_000, 6)) // Assuming 6 decimals for USDC
-        },
-        USDT => {
-            Some(DecimalAmount::new(1_000_000, 6)) // Assuming 6 decimals for USDT
-        },
-        WSOL => {
-            let url = format!("https://hermes.pyth.network/v2/updates/price/{}?ids%5B%5D={}", timestamp, PYTH_SOL_USD_PRICE_FEED_ID);
-            retry(&url, 3).await
-        },
-        _ => {
-            None
+        let price = res
+            .json::<PythParsedPrice>()
+            .await
+            .map_err(|e| PriceError::InvalidResponse(e.to_string()))?;
+        if let Some(parsed_price) = price.parsed.first()
+            && let Ok(price_in_int) = parsed_price.price.price.parse::<i64>()
+            && let Ok(conf) = parsed_price.price.conf.parse::<u64>()
+        {
+            return Ok(PythPriceData {
+                price: price_in_int,
+                conf,
+                expo: parsed_price.price.expo,
+                publish_time: parsed_price.price.publish_time,
+            });
         }
+        return Err(PriceError::InvalidResponse(format!("Unexpected pyth response from {}", url)));
     }
+    Err(last_error)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::price::oracle::{PriceOracle, PriceOracleChain};
+    use crate::price::{stablecoin::StablecoinPriceOracle, static_price::StaticPriceOracle};
 
     #[tokio::test]
     async fn test_get_price() {
-        let price = get_price(USDC, 0).await;
-        assert!(price.is_some());
-        assert_eq!(price.unwrap().to_float(), 1.0);
-        
-        let price = get_price(WSOL, 1717532000).await;
-        assert!(price.is_some());
+        // USDC 的 feed 在锚定容差内，按 1 USD 计；WSOL 交给下一个来源
+        let (usdc, wsol) = (Pubkey::from_str_const(USDC), Pubkey::from_str_const(WSOL));
+        let feed = StaticPriceOracle::new(HashMap::from([(usdc, DecimalAmount::new(99_950, 5))]));
+        let oracle = PriceOracleChain::new(vec![
+            Arc::new(StablecoinPriceOracle::new(Box::new(feed), HashSet::from([usdc]), 0.005, 600)),
+            Arc::new(StaticPriceOracle::new(HashMap::from([(wsol, DecimalAmount::new(17_195, 2))]))),
+        ]);
+
+        let price = oracle.historical_price(&usdc, 0).await;
+        assert!(price.is_ok());
+        assert_eq!(price.unwrap().price.to_float(), 1.0);
+
+        let price = oracle.historical_price(&wsol, 1717532000).await;
+        assert!(price.is_ok());
+        assert_eq!(price.unwrap().price.to_float(), 171.95);
+    }
+
+    #[test]
+    fn test_pyth_price_exponent() {
+        let price = PythPriceData { price: 14_523_000_000, conf: 7_250_000, expo: -8, publish_time: 0 };
+        assert_eq!(price.to_decimal_amount().unwrap().to_float(), 145.23);
+        assert!((price.confidence() - 0.0725).abs() < 1e-12);
+
+        let price = PythPriceData { price: 99_980, conf: 20, expo: -5, publish_time: 0 };
+        assert_eq!(price.to_decimal_amount().unwrap(), DecimalAmount::new(99_980, 5));
+
+        let price = PythPriceData { price: 12, conf: 1, expo: 2, publish_time: 0 };
+        assert_eq!(price.to_decimal_amount().unwrap(), DecimalAmount::new(1_200, 0));
+        assert_eq!(price.confidence(), 100.0);
+
+        let price = PythPriceData { price: -1, conf: 1, expo: -8, publish_time: 0 };
+        assert!(price.to_decimal_amount().is_err());
+        let price = PythPriceData { price: 1, conf: 1, expo: -300, publish_time: 0 };
+        assert!(price.to_decimal_amount().is_err());
+    }
+
+    #[test]
+    fn test_parse_hermes_response() {
+        let response = serde_json::from_str::<PythParsedPrice>(r#"{
+            "parsed": [{
+                "id": "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d",
+                "price": { "price": "17195470000", "conf": "9567290", "expo": -8, "publish_time": 1717532000 },
+                "ema_price": { "price": "17137563000", "conf": "10325416", "expo": -8, "publish_time": 1717532000 }
+            }]
+        }"#).unwrap();
+        let price = &response.parsed[0].price;
+        assert_eq!((price.expo, price.publish_time), (-8, 1_717_532_000));
     }
 }
 
@@ -1,20 +1,88 @@
-use crate::{handle::{base_token, handle_tx}, utils::f64_tool::{f64_keep_two, f64_to_percentage}};
+use crate::{error::app_error::PnlError, handle::handle_tx, price::{implied::ImpliedPrices, oracle::PriceOracle}, tx::{coverage::Coverage, jupiterv6_indexer::SignatureWindow}, utils::f64_tool::{f64_keep_two, f64_to_percentage}};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// 非交易转入的成本计算方式：zeroCost 按零成本计入，marketPrice 按转入时的市场价计入，excluded 忽略所有转入转出
+// 除 excluded 外，转出均按平均成本减少持仓，不产生已实现盈亏
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CostBasisPolicy {
+    ZeroCost,
+    MarketPrice,
+    #[default]
+    Excluded,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Pnl {
+    pub token_name: Option<String>,// Metaplex metadata 中的名称与符号，没有 metadata 时为 None
+    pub token_symbol: Option<String>,
     pub average_cost: Option<f64>,
     pub profit_loss_percentage: Option<String>,
     pub profit_loss_value: Option<f64>,
+    pub profit_loss_confidence: Option<f64>,// 已实现盈亏的估值不确定度（±USD），由 Pyth 价格的置信区间推算
     pub unrealized_profit_loss_value: Option<f64>,
+    pub network_fee_usd_value: f64,// 区间内所有 swap 交易支付的网络费用（基础费用 + 优先费）
+    pub unrealized_network_fee_usd_value: f64,// 仍在持仓中的买入与无法定价的交易支付的网络费用，不从净值中扣除
+    pub net_profit_loss_value: Option<f64>,// 已实现盈亏扣除卖出交易及已卖出部分对应买入交易的网络费用后的净值，profit_loss_value 为毛利
+    pub net_profit_loss_percentage: Option<String>,
+    pub cost_basis: CostBasisPolicy,
+    pub transferred_in_amount: f64,// 计入持仓的转入数量
+    pub transferred_out_amount: f64,// 从持仓中扣除的转出数量
+    pub window: Option<SignatureWindow>,// 本次统计实际使用的签名区间
+    pub coverage: Option<Coverage>,
+    pub complete: bool,// false 表示结果只基于部分交易（签名被截断、交易获取或解析失败）
+}
+
+// 转入代币的单位成本：zeroCost 为 0；marketPrice 优先使用转入时的历史价格，
+// 不支持的代币使用时间上最接近（不超过 implied_max_distance 秒）的一笔交易的隐含价格，都没有时按 0 计
+async fn transfer_in_prices(
+    mint: &solana_sdk::pubkey::Pubkey,
+    swap_items: &[handle_tx::SwapItem],
+    cost_basis: CostBasisPolicy,
+    price_oracle: &dyn PriceOracle,
+    implied_max_distance: Option<u64>,
+) -> Vec<f64> {
+    let mut prices = vec![0.0; swap_items.len()];
+    if cost_basis != CostBasisPolicy::MarketPrice {
+        return prices;
+    }
+    let mut implied = ImpliedPrices::from_items(mint, swap_items);
+    if let Some(max_distance) = implied_max_distance {
+        implied = implied.with_max_distance(max_distance);
+    }
+
+    for (i, item) in swap_items.iter().enumerate() {
+        if item.transfer_in_amount <= 0.0 {
+            continue;
+        }
+        let historical = price_oracle.historical_price(mint, item.timestamp)
+            .await
+            .map(|quote| quote.price.to_float())
+            .ok();
+        let nearest = implied.historical_price(mint, item.timestamp)
+            .await
+            .map(|quote| quote.price.to_float())
+            .ok();
+        prices[i] = match historical.or(nearest) {
+            Some(price) => price,
+            None => {
+                eprintln!("No market price for transfer of {} in {}, using zero cost", mint, item.sig);
+                0.0
+            }
+        };
+    }
+    prices
 }
 
 impl Pnl {
     pub async fn new(
         mint: solana_sdk::pubkey::Pubkey,
         swap_items: Vec<handle_tx::SwapItem>,
-    ) -> Self {
+        cost_basis: CostBasisPolicy,
+        price_oracle: &dyn PriceOracle,
+        implied_max_distance: Option<u64>,
+    ) -> Result<Self, PnlError> {
 
         #[cfg(test)]
         for item in &swap_items {
@@ -22,38 +90,63 @@ impl Pnl {
         }
 
         let mut pnl = Pnl {
-            average_cost: None,
-            profit_loss_percentage: None,
-            profit_loss_value: None,
-            unrealized_profit_loss_value: None,
+            cost_basis,
+            ..Pnl::default()
         };
+        let transfer_prices = transfer_in_prices(&mint, &swap_items, cost_basis, price_oracle, implied_max_distance).await;
 
         let mut sum_amount = 0.0;
         let mut sum_buy_amount = 0.0;
         let mut sum_buy_usd_value = 0.0;
         let mut sum_sell_amount = 0.0;
         let mut sum_sell_usd_value = 0.0;
+        let mut sum_buy_usd_confidence = 0.0;
+        let mut sum_sell_usd_confidence = 0.0;
+        let mut sum_network_fee_usd_value = 0.0;
+        let mut sum_buy_network_fee_usd_value = 0.0;
+        let mut sum_sell_network_fee_usd_value = 0.0;
+
+        swap_items.iter().zip(transfer_prices).for_each(|(item, transfer_price)| {
+            sum_network_fee_usd_value += item.network_fee_usd_value;
+            // 买入的网络费用与成本一样按已卖出的比例计入已实现部分，无法定价的交易不计入
+            if item.sell_amount != 0.0 {
+                sum_sell_network_fee_usd_value += item.network_fee_usd_value;
+            } else if item.buy_amount != 0.0 {
+                sum_buy_network_fee_usd_value += item.network_fee_usd_value;
+            }
+
+            if cost_basis != CostBasisPolicy::Excluded {
+                if item.transfer_in_amount > 0.0 {
+                    sum_amount += item.transfer_in_amount;
+                    sum_buy_amount += item.transfer_in_amount;
+                    sum_buy_usd_value += item.transfer_in_amount * transfer_price;
+                    pnl.transferred_in_amount += item.transfer_in_amount;
+                }
+                // 转出按平均成本减少持仓，平均成本不变
+                if item.transfer_out_amount > 0.0 {
+                    let out_amount = item.transfer_out_amount.min(sum_amount);
+                    sum_amount -= out_amount;
+                    pnl.transferred_out_amount += out_amount;
+                }
+            }
 
-        swap_items.iter().for_each(|item| {
             sum_amount += item.buy_amount;
             sum_buy_usd_value += item.buy_usd_value;
             sum_buy_amount += item.buy_amount;
+            sum_buy_usd_confidence += item.buy_usd_confidence;
 
             // 由于RPC的获取范围，所以只统计该范围的买入卖出情况
-            match item.sell_amount == 0.0 {
-                false => {
-                    if item.sell_amount > sum_amount {
-                        sum_sell_amount += sum_amount;
-                        sum_sell_usd_value += sum_amount * item.sell_usd_value / item.sell_amount;
-                        sum_amount = 0.0;
-                    } else {
-                        sum_sell_amount += item.sell_amount;
-                        sum_sell_usd_value += item.sell_usd_value;
-                        sum_amount -= item.sell_amount;
-                    }
-                },
-                _ => {
-                    
+            if item.sell_amount != 0.0 {
+                if item.sell_amount > sum_amount {
+                    sum_sell_amount += sum_amount;
+                    sum_sell_usd_value += sum_amount * item.sell_usd_value / item.sell_amount;
+                    sum_sell_usd_confidence += sum_amount * item.sell_usd_confidence / item.sell_amount;
+                    sum_amount = 0.0;
+                } else {
+                    sum_sell_amount += item.sell_amount;
+                    sum_sell_usd_value += item.sell_usd_value;
+                    sum_sell_usd_confidence += item.sell_usd_confidence;
+                    sum_amount -= item.sell_amount;
                 }
             }
 
@@ -63,22 +156,22 @@ impl Pnl {
             pnl.average_cost = Some(sum_buy_usd_value / sum_buy_amount);
         }
 
-        let current_price = base_token::retry_get_jupiter_price(
-            &mint.to_string(), 
-            &format!(
-                "https://lite-api.jup.ag/price/v3?ids={}",
-                mint.to_string()
-            ), 
-            5
-        ).await;
+        // 没有任何可估值的买入或卖出
+        if swap_items.iter().all(|item| item.buy_amount == 0.0 && item.sell_amount == 0.0) {
+            return Err(PnlError::NoSwaps(mint.to_string()));
+        }
+
+        // 当前价格获取失败只影响未实现盈亏
+        let current_price = price_oracle.spot_price(&mint)
+            .await
+            .map_err(|e| eprintln!("Error fetching spot price for {}: {}", mint, e))
+            .ok();
 
         if sum_sell_amount > 0.0 && sum_buy_amount > 0.0 {
             pnl.profit_loss_value = Some((sum_sell_usd_value / sum_sell_amount - sum_buy_usd_value / sum_buy_amount) * sum_sell_amount);
-            pnl.unrealized_profit_loss_value = if let Some(price) = current_price {
-                Some(sum_amount * (price - sum_buy_usd_value / sum_buy_amount))
-            } else {
-                None
-            };
+            // 卖出所得与已卖出部分的成本各自的不确定度相加
+            pnl.profit_loss_confidence = Some(f64_keep_two(sum_sell_usd_confidence + sum_buy_usd_confidence / sum_buy_amount * sum_sell_amount));
+            pnl.unrealized_profit_loss_value = current_price.map(|price| sum_amount * (price - sum_buy_usd_value / sum_buy_amount));
             pnl.profit_loss_percentage = if let Some(profit) = pnl.profit_loss_value {
                 if sum_buy_usd_value > 0.0 {
                     Some(f64_to_percentage(profit / sum_buy_usd_value * 100.0))
@@ -90,31 +183,148 @@ impl Pnl {
             };
         }
 
+        let realized_network_fee_usd_value = if sum_buy_amount > 0.0 {
+            sum_sell_network_fee_usd_value + sum_buy_network_fee_usd_value / sum_buy_amount * sum_sell_amount
+        } else {
+            sum_sell_network_fee_usd_value
+        };
+        pnl.network_fee_usd_value = f64_keep_two(sum_network_fee_usd_value);
+        pnl.unrealized_network_fee_usd_value = f64_keep_two(sum_network_fee_usd_value - realized_network_fee_usd_value);
+        pnl.net_profit_loss_value = pnl.profit_loss_value.map(|profit| profit - realized_network_fee_usd_value);
+        if let Some(net_profit) = pnl.net_profit_loss_value
+            && sum_buy_usd_value > 0.0
+        {
+            pnl.net_profit_loss_percentage = Some(f64_to_percentage(net_profit / sum_buy_usd_value * 100.0));
+        }
+
         // 保留两位小数
         if let Some(avg_cost) = pnl.average_cost {
             pnl.average_cost = Some(f64_keep_two(avg_cost));
         }
 
+        pnl.transferred_in_amount = f64_keep_two(pnl.transferred_in_amount);
+        pnl.transferred_out_amount = f64_keep_two(pnl.transferred_out_amount);
+
         if let Some(pl_value) = pnl.profit_loss_value {
             pnl.profit_loss_value = Some(f64_keep_two(pl_value));
         }
 
+        if let Some(net_pl_value) = pnl.net_profit_loss_value {
+            pnl.net_profit_loss_value = Some(f64_keep_two(net_pl_value));
+        }
+
         if let Some(unrealized_pl_value) = pnl.unrealized_profit_loss_value {
             pnl.unrealized_profit_loss_value = Some(f64_keep_two(unrealized_pl_value));
         }
 
-        pnl
+        Ok(pnl)
     }
 }
 
-impl Default for Pnl {
-    fn default() -> Self {
-        Pnl {
-            average_cost: None,
-            profit_loss_percentage: None,
-            profit_loss_value: None,
-            unrealized_profit_loss_value: None,
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+    use crate::config::app_config::PriceConfig;
+    use crate::handle::handle_tx::SwapItem;
+    use crate::price::oracle::PriceOracleChain;
+
+    fn swap(timestamp: u64, buy: (f64, f64), sell: (f64, f64)) -> SwapItem {
+        SwapItem {
+            amount: buy.0 - sell.0,
+            usd_value: buy.1 - sell.1,
+            buy_amount: buy.0,
+            buy_usd_value: buy.1,
+            sell_amount: sell.0,
+            sell_usd_value: sell.1,
+            ..SwapItem::transfer(Pubkey::default(), 0.0, timestamp, Signature::default())
         }
     }
-}
 
+    // 价格接口不可用时未实现盈亏为 None，不影响已实现部分
+    fn offline_oracle() -> PriceOracleChain {
+        PriceOracleChain::from_config(&PriceConfig {
+            jupiter_base_url: "http://127.0.0.1:9".to_string(),
+            pyth_base_url: "http://127.0.0.1:9".to_string(),
+            retries: 1,
+            ..PriceConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_transfer_cost_basis() {
+        let mint = Pubkey::new_unique();
+        // 买入 10 个（100 USD），收到转入 10 个，卖出 20 个（300 USD）
+        let items = || vec![
+            swap(1, (10.0, 100.0), (0.0, 0.0)),
+            SwapItem::transfer(mint, 10.0, 2, Signature::default()),
+            swap(3, (0.0, 0.0), (20.0, 300.0)),
+        ];
+
+        // 忽略转入时卖出数量被截断为已知的买入数量
+        let pnl = Pnl::new(mint, items(), CostBasisPolicy::Excluded, &offline_oracle(), None).await.unwrap();
+        assert_eq!(pnl.profit_loss_value, Some(50.0));
+        assert_eq!(pnl.transferred_in_amount, 0.0);
+
+        let pnl = Pnl::new(mint, items(), CostBasisPolicy::ZeroCost, &offline_oracle(), None).await.unwrap();
+        assert_eq!(pnl.average_cost, Some(5.0));
+        assert_eq!(pnl.profit_loss_value, Some(200.0));
+        assert_eq!(pnl.transferred_in_amount, 10.0);
+
+        // 不支持的代币使用最接近的成交价（买入价 10）
+        let pnl = Pnl::new(mint, items(), CostBasisPolicy::MarketPrice, &offline_oracle(), None).await.unwrap();
+        assert_eq!(pnl.average_cost, Some(10.0));
+        assert_eq!(pnl.profit_loss_value, Some(100.0));
+
+        // 超过最大时间差的隐含价格不使用，按零成本计入
+        let pnl = Pnl::new(mint, items(), CostBasisPolicy::MarketPrice, &offline_oracle(), Some(0)).await.unwrap();
+        assert_eq!(pnl.average_cost, Some(5.0));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_out_reduces_position() {
+        let mint = Pubkey::new_unique();
+        let items = vec![
+            swap(1, (10.0, 100.0), (0.0, 0.0)),
+            SwapItem::transfer(mint, -6.0, 2, Signature::default()),
+            swap(3, (0.0, 0.0), (10.0, 150.0)),
+        ];
+        let pnl = Pnl::new(mint, items, CostBasisPolicy::ZeroCost, &offline_oracle(), None).await.unwrap();
+        assert_eq!(pnl.transferred_out_amount, 6.0);
+        // 只有剩余的 4 个计入卖出
+        assert_eq!(pnl.profit_loss_value, Some(20.0));
+    }
+
+    #[tokio::test]
+    async fn test_profit_loss_confidence() {
+        let mint = Pubkey::new_unique();
+        let items = vec![
+            SwapItem { buy_usd_confidence: 2.0, ..swap(1, (10.0, 100.0), (0.0, 0.0)) },
+            SwapItem { sell_usd_confidence: 1.0, ..swap(2, (0.0, 0.0), (5.0, 75.0)) },
+        ];
+        let pnl = Pnl::new(mint, items, CostBasisPolicy::Excluded, &offline_oracle(), None).await.unwrap();
+        assert_eq!(pnl.profit_loss_value, Some(25.0));
+        // 卖出 ±1，已卖出一半的买入成本 ±1
+        assert_eq!(pnl.profit_loss_confidence, Some(2.0));
+    }
+
+    #[tokio::test]
+    async fn test_network_fee_with_open_position() {
+        let mint = Pubkey::new_unique();
+        let items = vec![
+            SwapItem { network_fee_usd_value: 1.0, ..swap(1, (10.0, 100.0), (0.0, 0.0)) },
+            SwapItem { network_fee_usd_value: 1.0, ..swap(2, (10.0, 100.0), (0.0, 0.0)) },
+            SwapItem { network_fee_usd_value: 0.5, ..swap(3, (0.0, 0.0), (10.0, 150.0)) },
+            // 无法定价的交易
+            SwapItem { network_fee_usd_value: 0.25, ..swap(4, (0.0, 0.0), (0.0, 0.0)) },
+        ];
+        let pnl = Pnl::new(mint, items, CostBasisPolicy::Excluded, &offline_oracle(), None).await.unwrap();
+        assert_eq!(pnl.profit_loss_value, Some(50.0));
+        assert_eq!(pnl.network_fee_usd_value, 2.75);
+        // 卖出 0.5 + 已卖出一半的买入 1.0；持仓中的买入与无法定价的交易不计入
+        assert_eq!(pnl.net_profit_loss_value, Some(48.5));
+        assert_eq!(pnl.unrealized_network_fee_usd_value, 1.25);
+    }
+}
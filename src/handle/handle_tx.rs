@@ -1,13 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap};
 
 use solana_sdk::{pubkey::Pubkey, signature::Signature};
 
 use crate::handle::base_token::{self, DecimalAmount};
+use crate::handle::route::{self, NetTrade};
+use crate::price::{inference, oracle::{PriceOracle, PriceQuote}};
+use crate::tx::network_fee;
 
 #[derive(Debug, Clone)]
 pub struct SwapSumInfos {
     pub swap_data: Vec<crate::tx::inner_tx::SwapInstruction>,
+    pub fee_data: Vec<crate::tx::inner_tx::FeeEvent>,
     pub token_data: Vec<crate::tx::post_balance::UserBalanceInfo>,
+    pub network_fee: u64,// 用户支付的网络费用（基础费用 + 优先费，lamports）
+    pub trade: NetTrade,// 用于估值的净交易，默认由 swap 事件合并得到
+    pub transfer_fee: u64,// 目标 mint 为带转账费用的 Token-2022 时，买入到账时被扣除的转账费用（最小单位）
+    pub mint_decimals: HashMap<Pubkey, u8>,// 从链上 mint 账户读取的精度，补充 token_data 中没有的 mint
     pub timestamp: u64,
     pub sig: Signature,
 }
@@ -15,17 +23,42 @@ pub struct SwapSumInfos {
 impl SwapSumInfos {
     pub fn new(
         swap_data: Vec<crate::tx::inner_tx::SwapInstruction>,
+        fee_data: Vec<crate::tx::inner_tx::FeeEvent>,
         token_data: Vec<crate::tx::post_balance::UserBalanceInfo>,
+        network_fee: u64,
         timestamp: u64,
         sig: Signature,
     ) -> Self {
         SwapSumInfos {
+            trade: route::net_trade(&swap_data),
+            transfer_fee: 0,
+            mint_decimals: HashMap::new(),
             swap_data,
+            fee_data,
             token_data,
+            network_fee,
             timestamp,
             sig,
         }
     }
+
+    // swap 事件中的数量为转账前的数量，实际到账需要扣除转账费用；余额变化推算的交易已是实际到账数量，不需要设置
+    pub fn with_transfer_fee(mut self, transfer_fee: u64) -> Self {
+        self.transfer_fee = transfer_fee;
+        self
+    }
+
+    // 对手方代币账户在同一笔交易中关闭时 token balances 中没有该 mint 的精度
+    pub fn with_mint_decimals(mut self, mint_decimals: HashMap<Pubkey, u8>) -> Self {
+        self.mint_decimals = mint_decimals;
+        self
+    }
+
+    // 没有 swap 事件时使用余额变化推算的净交易
+    pub fn with_trade(mut self, trade: NetTrade) -> Self {
+        self.trade = trade;
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -39,22 +72,32 @@ pub struct SwapItem {
     pub sell_amount: f64, // Amount sold in USD
     pub buy_usd_value: f64, // USD value of the amount bought
     pub sell_usd_value: f64, // USD value of the amount sold
+    pub buy_usd_confidence: f64, // 买入金额的估值不确定度（±USD），由对手方代币价格的置信区间推算
+    pub sell_usd_confidence: f64, // 卖出金额的估值不确定度（±USD）
+    pub fee_usd_value: f64, // 平台/推荐人费用的 USD 价值，已计入 buy_usd_value 或从 sell_usd_value 中扣除
+    pub network_fee_usd_value: f64, // 网络费用按交易时 SOL 价格折算的 USD 价值，单独统计，不计入买卖金额
+    pub transfer_in_amount: f64, // 非交易转入（空投、其他钱包、交易所提币），成本由 CostBasisPolicy 决定
+    pub transfer_out_amount: f64, // 非交易转出，按平均成本减少持仓
+    pub implied_price: Option<f64>, // 本笔交易中目标代币与已定价代币之间 swap 的隐含 USD 价格
 }
 
 impl SwapItem {
     pub async fn new(
         mint: Pubkey,
-        swap_sum_infos: SwapSumInfos
+        swap_sum_infos: SwapSumInfos,
+        price_oracle: &dyn PriceOracle,
     ) -> Self {
 
         #[cfg(test)]
         println!("Handle Tx: {}", swap_sum_infos.sig);
 
-        let mut token_decimals = HashMap::new();
+        let mut token_decimals = swap_sum_infos.mint_decimals.clone();
         let mut token_prices = HashMap::new();
         swap_sum_infos.token_data.iter().for_each(|a|{
             token_decimals.insert(a.mint, a.decimals);
         });
+        // 包装后随即关闭的 WSOL 账户不会出现在 token balances 中
+        token_decimals.entry(Pubkey::from_str_const(base_token::WSOL)).or_insert(9);
 
         let mut amount = 0.0;
         let mut usd_value:f64 = 0.0;
@@ -62,92 +105,330 @@ impl SwapItem {
         let mut sell_amount = 0.0;
         let mut buy_usd_value = 0.0;
         let mut sell_usd_value = 0.0;
+        let mut buy_usd_confidence = 0.0;
+        let mut sell_usd_confidence = 0.0;
 
-        for a in &swap_sum_infos.swap_data {
-            if a.input_mint == mint {
-                let output_mint = &a.output_mint;
-                let output_mint_decimals = token_decimals.get(output_mint).unwrap_or(&0);
-                let input_mint_decimals = token_decimals.get(&a.input_mint).unwrap_or(&0);
-                if *output_mint_decimals == 0 || *input_mint_decimals == 0 {
-                    continue; // Skip if decimals are not found
-                }
-                let output_amount = DecimalAmount::new(
-                    a.output_amount,
-                    *output_mint_decimals,
-                );
-                let input_amount = DecimalAmount::new(
-                    a.input_amount,
-                    *input_mint_decimals,
-                );
-                if token_prices.get(output_mint).is_none() {
-                    let mint_price = base_token::get_price(&output_mint.to_string(), swap_sum_infos.timestamp).await;
-                    if let Some(price) = mint_price {
-                        token_prices.insert(output_mint.clone(), price);
+        // 多跳路由合并为净交易，USDC→SOL→TOKEN 按 USDC 买入估值
+        let trade = &swap_sum_infos.trade;
+        let net_amount = trade.net_amount(&mint);
+        let mint_decimals = token_decimals.get(&mint).copied();
+        // 买入时对手方为净流出的代币，卖出时为净流入的代币
+        let counter_legs = if net_amount > 0 { &trade.inputs } else { &trade.outputs };
+
+        if net_amount != 0
+            && let Some(mint_decimals) = mint_decimals
+            && !counter_legs.is_empty()
+        {
+            let mut counter_usd_value = 0.0;
+            let mut counter_usd_confidence = 0.0;
+            let mut priced = true;
+            for leg in counter_legs {
+                let Some(decimals) = token_decimals.get(&leg.mint).copied() else {
+                    priced = false; // Skip if decimals are not found
+                    break;
+                };
+                let price = match cached_price(&mut token_prices, &leg.mint, swap_sum_infos.timestamp, price_oracle).await {
+                    Some(quote) => Some((quote.price.to_float(), quote.confidence)),
+                    None => inferred_price(&mut token_prices, &token_decimals, &leg.mint, &swap_sum_infos, price_oracle).await,
+                };
+                match price {
+                    Some((price, confidence)) => {
+                        let leg_amount = DecimalAmount::new(leg.amount, decimals).to_float();
+                        counter_usd_value += leg_amount * price;
+                        counter_usd_confidence += leg_amount * confidence;
+                    },
+                    None => {
+                        priced = false;
+                        break;
                     }
                 }
-                let mint_price = token_prices.get(output_mint).cloned();
-                if let Some(price) = mint_price {
-                    let input_amount_f64 = input_amount.to_float();
-                    amount -= input_amount_f64;
-                    sell_amount += input_amount_f64;
-
-                    #[cfg(test)]
-                    println!("Sell USD Value: {} {}", output_amount.to_float(), price.to_float());
+            }
 
-                    let sell_usd_value_f64 = output_amount * price;
-                    usd_value -= sell_usd_value_f64;
-                    sell_usd_value += sell_usd_value_f64;
-                }
+            let gross_amount = u64::try_from(net_amount.unsigned_abs()).unwrap_or(u64::MAX);
+            // 对手方无法定价时按目标代币自身的历史价格估值（预言机，或相邻交易中的隐含价格）
+            if !priced
+                && let Some(quote) = cached_price(&mut token_prices, &mint, swap_sum_infos.timestamp, price_oracle).await
+            {
+                let gross = DecimalAmount::new(gross_amount, mint_decimals).to_float();
+                counter_usd_value = gross * quote.price.to_float();
+                counter_usd_confidence = gross * quote.confidence;
+                priced = true;
             }
 
-            if a.output_mint == mint {
-                let input_mint = &a.input_mint;
-                let input_mint_decimals = token_decimals.get(input_mint).unwrap_or(&0);
-                let output_mint_decimals = token_decimals.get(&a.output_mint).unwrap_or(&0);
-                if *input_mint_decimals == 0 || *output_mint_decimals == 0 {
-                    continue; // Skip if decimals are not found
+            if priced {
+                let mut mint_amount = gross_amount;
+                // 买入的 Token-2022 代币按扣除转账费用后的到账数量计入；卖出时用户转出的就是事件中的数量
+                if net_amount > 0 {
+                    mint_amount = mint_amount.saturating_sub(swap_sum_infos.transfer_fee);
                 }
-                let input_amount = DecimalAmount::new(
-                    a.input_amount,
-                    *input_mint_decimals,
-                );
-                let output_amount = DecimalAmount::new(
-                    a.output_amount,
-                    *output_mint_decimals,
-                );
-                
-                if token_prices.get(input_mint).is_none() {
-                    let mint_price = base_token::get_price(&input_mint.to_string(), swap_sum_infos.timestamp).await;
-                    if let Some(price) = mint_price {
-                        token_prices.insert(input_mint.clone(), price);
-                    }
-                }
-                let mint_price = token_prices.get(input_mint).cloned();
-                if let Some(price) = mint_price {
-                    let output_amount_f64 = output_amount.to_float();
-                    amount += output_amount_f64;
-                    buy_amount += output_amount_f64;
+                let mint_amount = DecimalAmount::new(mint_amount, mint_decimals).to_float();
+                if net_amount > 0 {
+                    #[cfg(test)]
+                    println!("Buy USD Value: {} {}", mint_amount, counter_usd_value);
 
+                    amount += mint_amount;
+                    buy_amount += mint_amount;
+                    usd_value += counter_usd_value;
+                    buy_usd_value += counter_usd_value;
+                    buy_usd_confidence += counter_usd_confidence;
+                } else {
                     #[cfg(test)]
-                    println!("Buy USD Value: {} {}", input_amount.to_float(), price.to_float());
+                    println!("Sell USD Value: {} {}", mint_amount, counter_usd_value);
 
-                    let buy_usd_value_f64 = input_amount * price;
-                    usd_value += buy_usd_value_f64;
-                    buy_usd_value += buy_usd_value_f64;
+                    amount -= mint_amount;
+                    sell_amount += mint_amount;
+                    usd_value -= counter_usd_value;
+                    sell_usd_value += counter_usd_value;
+                    sell_usd_confidence += counter_usd_confidence;
                 }
             }
-        };
+        }
 
-        SwapItem {
+        let implied_price = inference::infer_price(&mint, &swap_sum_infos.swap_data, &token_decimals, &token_prices)
+            .map(|(price, _)| price);
+
+        let mut item = SwapItem {
             sig: swap_sum_infos.sig,
             timestamp: swap_sum_infos.timestamp,
-            mint: mint, // Placeholder, should be set appropriately
-            amount: amount, // Placeholder, should be set appropriately
-            usd_value: usd_value, // Placeholder, should be calculated based on the swap data
-            buy_amount: buy_amount,
-            sell_amount: sell_amount,
-            buy_usd_value: buy_usd_value,
-            sell_usd_value: sell_usd_value,
+            mint,
+            amount,
+            usd_value,
+            buy_amount,
+            sell_amount,
+            buy_usd_value,
+            sell_usd_value,
+            buy_usd_confidence,
+            sell_usd_confidence,
+            fee_usd_value: 0.0,
+            network_fee_usd_value: 0.0,
+            transfer_in_amount: 0.0,
+            transfer_out_amount: 0.0,
+            implied_price,
+        };
+
+        if swap_sum_infos.network_fee > 0 {
+            let wsol = Pubkey::from_str_const(base_token::WSOL);
+            match cached_price(&mut token_prices, &wsol, swap_sum_infos.timestamp, price_oracle).await {
+                Some(quote) => item.network_fee_usd_value = DecimalAmount::new(swap_sum_infos.network_fee, network_fee::SOL_DECIMALS) * quote.price,
+                None => eprintln!("No SOL price for network fee in {}", swap_sum_infos.sig),
+            }
+        }
+
+        for fee in &swap_sum_infos.fee_data {
+            let Some(fee_mint_decimals) = token_decimals.get(&fee.mint).copied() else {
+                continue; // Skip if decimals are not found
+            };
+            let fee_amount = DecimalAmount::new(fee.amount, fee_mint_decimals).to_float();
+
+            // 以目标代币收取的费用直接减少用户得到（或增加用户付出）的代币数量，成本与所得不变
+            if fee.mint == mint {
+                item.apply_mint_fee(fee_amount);
+                continue;
+            }
+
+            // 其他代币收取的费用按其价格估值
+            let fee_price = match cached_price(&mut token_prices, &fee.mint, swap_sum_infos.timestamp, price_oracle).await {
+                Some(quote) => Some(quote.price.to_float()),
+                None => inferred_price(&mut token_prices, &token_decimals, &fee.mint, &swap_sum_infos, price_oracle)
+                    .await
+                    .map(|(price, _)| price),
+            };
+
+            match fee_price {
+                Some(price) => item.apply_fee(fee_amount * price),
+                None => eprintln!("No price for fee mint {} in {}", fee.mint, swap_sum_infos.sig),
+            }
         }
+
+        item
     }
-}
\ No newline at end of file
+
+    // 没有 swap、只有该 mint 余额变化的交易，amount 为带符号的变化量
+    pub fn transfer(mint: Pubkey, amount: f64, timestamp: u64, sig: Signature) -> Self {
+        SwapItem {
+            sig,
+            timestamp,
+            mint,
+            amount,
+            usd_value: 0.0,
+            buy_amount: 0.0,
+            sell_amount: 0.0,
+            buy_usd_value: 0.0,
+            sell_usd_value: 0.0,
+            buy_usd_confidence: 0.0,
+            sell_usd_confidence: 0.0,
+            fee_usd_value: 0.0,
+            network_fee_usd_value: 0.0,
+            transfer_in_amount: amount.max(0.0),
+            transfer_out_amount: (-amount).max(0.0),
+            implied_price: None,
+        }
+    }
+
+    // 本笔交易中目标代币的成交价（USD）
+    pub fn executed_price(&self) -> Option<f64> {
+        if self.amount >= 0.0 && self.buy_amount > 0.0 {
+            Some(self.buy_usd_value / self.buy_amount)
+        } else if self.sell_amount > 0.0 {
+            Some(self.sell_usd_value / self.sell_amount)
+        } else {
+            None
+        }
+    }
+
+    // 目标代币收取的费用：买入时用户实际到账的数量少 fee_amount，卖出时用户多付出 fee_amount，
+    // 与 balance_delta::cross_check 的口径一致；按调整前的成交价记录费用的 USD 价值
+    fn apply_mint_fee(&mut self, fee_amount: f64) {
+        let Some(price) = self.executed_price() else {
+            return;
+        };
+        self.fee_usd_value += fee_amount * price;
+        self.amount -= fee_amount;
+        if self.buy_amount > 0.0 {
+            self.buy_amount = (self.buy_amount - fee_amount).max(0.0);
+        } else {
+            self.sell_amount += fee_amount;
+        }
+    }
+
+    // 其他代币收取的费用：净买入时计入买入成本，净卖出时从卖出所得中扣除
+    fn apply_fee(&mut self, fee_usd_value: f64) {
+        self.fee_usd_value += fee_usd_value;
+        if self.amount >= 0.0 && self.buy_amount > 0.0 {
+            self.buy_usd_value += fee_usd_value;
+            self.usd_value += fee_usd_value;
+        } else if self.sell_amount > 0.0 {
+            self.sell_usd_value -= fee_usd_value;
+            self.usd_value += fee_usd_value;
+        }
+    }
+}
+
+// 同一笔交易内缓存历史价格，获取失败返回 None
+async fn cached_price(
+    token_prices: &mut HashMap<Pubkey, PriceQuote>,
+    mint: &Pubkey,
+    timestamp: u64,
+    price_oracle: &dyn PriceOracle,
+) -> Option<PriceQuote> {
+    if let Entry::Vacant(entry) = token_prices.entry(*mint)
+        && let Ok(price) = price_oracle.historical_price(mint, timestamp).await
+    {
+        entry.insert(price);
+    }
+    token_prices.get(mint).cloned()
+}
+
+// 没有价格来源的代币按同一笔交易中与已定价代币之间的 swap 推算单价与置信区间
+async fn inferred_price(
+    token_prices: &mut HashMap<Pubkey, PriceQuote>,
+    token_decimals: &HashMap<Pubkey, u8>,
+    mint: &Pubkey,
+    swap_sum_infos: &SwapSumInfos,
+    price_oracle: &dyn PriceOracle,
+) -> Option<(f64, f64)> {
+    let swaps = &swap_sum_infos.swap_data;
+    for other in inference::counterparts(mint, swaps) {
+        cached_price(token_prices, &other, swap_sum_infos.timestamp, price_oracle).await;
+    }
+    inference::infer_price(mint, swaps, token_decimals, token_prices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(buy_amount: f64, buy_usd_value: f64, sell_amount: f64, sell_usd_value: f64) -> SwapItem {
+        SwapItem {
+            sig: Signature::default(),
+            timestamp: 0,
+            mint: Pubkey::new_unique(),
+            amount: buy_amount - sell_amount,
+            usd_value: buy_usd_value - sell_usd_value,
+            buy_amount,
+            sell_amount,
+            buy_usd_value,
+            sell_usd_value,
+            buy_usd_confidence: 0.0,
+            sell_usd_confidence: 0.0,
+            fee_usd_value: 0.0,
+            network_fee_usd_value: 0.0,
+            transfer_in_amount: 0.0,
+            transfer_out_amount: 0.0,
+            implied_price: None,
+        }
+    }
+
+    #[test]
+    fn test_fee_added_to_buy_cost() {
+        let mut buy = item(10.0, 100.0, 0.0, 0.0);
+        assert_eq!(buy.executed_price(), Some(10.0));
+        buy.apply_fee(1.5);
+        assert_eq!(buy.buy_usd_value, 101.5);
+        assert_eq!(buy.fee_usd_value, 1.5);
+    }
+
+    #[test]
+    fn test_fee_deducted_from_sell_proceeds() {
+        let mut sell = item(0.0, 0.0, 4.0, 40.0);
+        assert_eq!(sell.executed_price(), Some(10.0));
+        sell.apply_fee(2.0);
+        assert_eq!(sell.sell_usd_value, 38.0);
+        assert_eq!(sell.buy_usd_value, 0.0);
+    }
+
+    // 对手方代币没有任何价格来源时，按相邻交易中目标代币的隐含价格估值
+    #[tokio::test]
+    async fn test_unpriced_counter_leg_uses_implied_price() {
+        use std::sync::Arc;
+
+        use crate::price::{implied::ImpliedPrices, oracle::PriceOracleChain, static_price::StaticPriceOracle};
+        use crate::tx::inner_tx::SwapInstruction;
+
+        let (mint, counter) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let swap = SwapInstruction { amm: Pubkey::new_unique(), input_mint: counter, input_amount: 1_000, output_mint: mint, output_amount: 5_000_000 };
+        let infos = || SwapSumInfos::new(vec![swap.clone()], vec![], vec![], 0, 1_000, Signature::default())
+            .with_mint_decimals(HashMap::from([(mint, 6), (counter, 0)]));
+        let no_prices = PriceOracleChain::new(vec![Arc::new(StaticPriceOracle::new(HashMap::new()))]);
+
+        let item = SwapItem::new(mint, infos(), &no_prices).await;
+        assert_eq!(item.buy_amount, 0.0);
+
+        let mut implied = ImpliedPrices::default();
+        implied.record(mint, 900, 2.0);
+        let oracle = PriceOracleChain::new(vec![Arc::new(StaticPriceOracle::new(HashMap::new())), Arc::new(implied)]);
+        let item = SwapItem::new(mint, infos(), &oracle).await;
+        assert_eq!(item.buy_amount, 5.0);
+        assert_eq!(item.buy_usd_value, 10.0);
+    }
+
+    // 以目标代币收取的平台费用减少到账数量，不再计入买入成本
+    #[tokio::test]
+    async fn test_target_mint_fee_reduces_position() {
+        use std::sync::Arc;
+
+        use crate::price::{oracle::PriceOracleChain, static_price::StaticPriceOracle};
+        use crate::tx::inner_tx::{FeeEvent, SwapInstruction};
+
+        let (mint, usdc) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // 100 USDC 买入 10 个，其中 0.1 个作为平台费用
+        let swap = SwapInstruction { amm: Pubkey::new_unique(), input_mint: usdc, input_amount: 100_000_000, output_mint: mint, output_amount: 10_000_000 };
+        let fee = FeeEvent { account: Pubkey::new_unique(), mint, amount: 100_000 };
+        let infos = SwapSumInfos::new(vec![swap], vec![fee], vec![], 0, 1_000, Signature::default())
+            .with_mint_decimals(HashMap::from([(mint, 6), (usdc, 6)]));
+        let oracle = PriceOracleChain::new(vec![Arc::new(StaticPriceOracle::new(HashMap::from([(usdc, DecimalAmount::new(1_000_000, 6))])))]);
+
+        let buy = SwapItem::new(mint, infos, &oracle).await;
+        assert!((buy.amount - 9.9).abs() < 1e-9);
+        assert!((buy.buy_amount - 9.9).abs() < 1e-9);
+        assert_eq!(buy.buy_usd_value, 100.0);
+        assert!((buy.fee_usd_value - 1.0).abs() < 1e-9);
+
+        // 卖出时用户多付出费用部分的代币，卖出所得不变
+        let mut sell = item(0.0, 0.0, 4.0, 40.0);
+        sell.apply_mint_fee(0.5);
+        assert_eq!(sell.sell_amount, 4.5);
+        assert_eq!(sell.amount, -4.5);
+        assert_eq!(sell.sell_usd_value, 40.0);
+    }
+}
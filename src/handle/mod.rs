@@ -1,3 +1,4 @@
 pub mod base_token;
 pub mod handle_tx;
-pub mod handle_swap_item;
\ No newline at end of file
+pub mod handle_swap_item;
+pub mod route;
\ No newline at end of file
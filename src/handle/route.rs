@@ -0,0 +1,95 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::tx::inner_tx::SwapInstruction;
+
+// 合并后某个 mint 的净流入或净流出数量（最小单位）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leg {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+// 一笔交易中所有 swap 事件合并后的净交易：多跳路由 A→B→C 的中间代币相互抵消，只剩 A 的流出与 C 的流入
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetTrade {
+    pub inputs: Vec<Leg>,
+    pub outputs: Vec<Leg>,
+}
+
+impl NetTrade {
+    // 某个 mint 的净变化，正数为净流入
+    pub fn net_amount(&self, mint: &Pubkey) -> i128 {
+        let input = self.inputs.iter().find(|leg| leg.mint == *mint).map_or(0, |leg| leg.amount);
+        let output = self.outputs.iter().find(|leg| leg.mint == *mint).map_or(0, |leg| leg.amount);
+        i128::from(output) - i128::from(input)
+    }
+}
+
+// 按 mint 汇总所有 swap 事件的输入输出，顺序与 mint 首次出现的顺序一致
+pub fn net_trade(swaps: &[SwapInstruction]) -> NetTrade {
+    let mut flows: Vec<(Pubkey, i128)> = vec![];
+    let mut add = |mint: Pubkey, delta: i128| {
+        match flows.iter_mut().find(|(m, _)| *m == mint) {
+            Some((_, flow)) => *flow += delta,
+            None => flows.push((mint, delta)),
+        }
+    };
+    for swap in swaps {
+        add(swap.input_mint, -i128::from(swap.input_amount));
+        add(swap.output_mint, i128::from(swap.output_amount));
+    }
+
+    let mut trade = NetTrade::default();
+    for (mint, flow) in flows {
+        let amount = u64::try_from(flow.unsigned_abs()).unwrap_or(u64::MAX);
+        if flow > 0 {
+            trade.outputs.push(Leg { mint, amount });
+        } else if flow < 0 {
+            trade.inputs.push(Leg { mint, amount });
+        }
+    }
+    trade
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn swap(input_mint: Pubkey, input_amount: u64, output_mint: Pubkey, output_amount: u64) -> SwapInstruction {
+        SwapInstruction {
+            amm: Pubkey::new_unique(),
+            input_mint,
+            input_amount,
+            output_mint,
+            output_amount,
+        }
+    }
+
+    #[test]
+    fn test_multi_hop_route() {
+        let (usdc, sol, token) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        // USDC→SOL→TOKEN，其中 SOL→TOKEN 拆分到两个 AMM
+        let trade = net_trade(&[
+            swap(usdc, 100_000_000, sol, 500_000_000),
+            swap(sol, 300_000_000, token, 7_000),
+            swap(sol, 200_000_000, token, 3_000),
+        ]);
+        assert_eq!(trade.inputs, vec![Leg { mint: usdc, amount: 100_000_000 }]);
+        assert_eq!(trade.outputs, vec![Leg { mint: token, amount: 10_000 }]);
+        assert_eq!(trade.net_amount(&token), 10_000);
+        assert_eq!(trade.net_amount(&usdc), -100_000_000);
+        assert_eq!(trade.net_amount(&sol), 0);
+    }
+
+    #[test]
+    fn test_round_trip_cancels_out() {
+        let (usdc, token) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let trade = net_trade(&[
+            swap(usdc, 100, token, 50),
+            swap(token, 50, usdc, 101),
+        ]);
+        assert!(trade.inputs.is_empty());
+        assert_eq!(trade.outputs, vec![Leg { mint: usdc, amount: 1 }]);
+        assert_eq!(trade.net_amount(&token), 0);
+    }
+}
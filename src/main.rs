@@ -2,37 +2,60 @@
 pub mod tx;
 pub mod handle;
 pub mod utils;
+pub mod config;
+pub mod error;
+pub mod price;
 
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
 use axum::{
     routing::get,
-    http::StatusCode,
     Json, Router,
-    extract::Query
+    extract::{Query, State}
 };
 use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
 
-use crate::handle::handle_swap_item::Pnl;
+use crate::config::app_config::Config;
+use crate::error::app_error::{AppError, IndexerError};
+use crate::handle::handle_swap_item::{CostBasisPolicy, Pnl};
+use crate::tx::jupiterv6_indexer::{JupiterV6Indexer, SignatureBound};
+use crate::tx::rpc_pool::EndpointMetrics;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct UserInfo {
     pub user_address: String,
     pub token_mint: String,
+    pub max_signatures: Option<usize>,// 最多回溯的签名数量，不传则使用配置中的默认值
+    pub since: Option<i64>,// 只统计该时间戳（秒）之后的交易
+    #[serde(default)]
+    pub cost_basis: CostBasisPolicy,// 转入代币的成本计算方式，默认忽略转入转出
+}
+
+struct AppState {
+    config: Arc<Config>,
+    indexer: JupiterV6Indexer,
 }
 
 #[tokio::main]
 async fn main() {
-    
+
     tracing_subscriber::fmt::init();
-    
+
+    let config = Arc::new(Config::load().expect("Failed to load config"));
+    let state = Arc::new(AppState {
+        config: Arc::clone(&config),
+        indexer: JupiterV6Indexer::new(Arc::clone(&config)),
+    });
+
     let app = Router::new()
         .route("/", get(root))
-        .route("/pnl", get(get_pnl));
-    
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:80").await.unwrap();
+        .route("/pnl", get(get_pnl))
+        .route("/rpc/metrics", get(get_rpc_metrics))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(&config.listen_addr).await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
 
@@ -40,21 +63,30 @@ async fn root() -> &'static str {
     "Hello, Jupiter!"
 }
 
+async fn get_rpc_metrics(
+    State(state): State<Arc<AppState>>,
+) -> Json<Vec<EndpointMetrics>> {
+    Json(state.indexer.rpc_metrics())
+}
+
 async fn get_pnl(
+    State(state): State<Arc<AppState>>,
     Query(user_info): Query<UserInfo>,
-) -> (StatusCode, Json<Pnl>) {
+) -> Result<Json<Pnl>, AppError> {
 
     println!("User Info: {:?}", user_info);
-    let jupiterv6_indexer_im = tx::jupiterv6_indexer::JupiterV6Indexer::new();
-    let mint = Pubkey::from_str(&user_info.token_mint);
-    let user = Pubkey::from_str(&user_info.user_address);
-    if mint.is_err() || user.is_err() {
-        return (StatusCode::BAD_REQUEST, Json(Pnl::default()));
+    let mint = Pubkey::from_str(&user_info.token_mint)
+        .map_err(|_| IndexerError::InvalidPubkey(user_info.token_mint.clone()))?;
+    let user = Pubkey::from_str(&user_info.user_address)
+        .map_err(|_| IndexerError::InvalidPubkey(user_info.user_address.clone()))?;
+    let bound = SignatureBound {
+        max_signatures: user_info.max_signatures.or(state.config.rpc.max_signatures),
+        since: user_info.since,
+    };
+    let res = state.indexer.get_jupiter_v6_txs(&user, &mint, bound, user_info.cost_basis).await;
+    if let Err(e) = &res {
+        eprintln!("Error computing pnl for {}: {}", user_info.user_address, e);
     }
-    let res = jupiterv6_indexer_im.get_jupiter_v6_txs(&user.unwrap(), &mint.unwrap()).await;
 
-    (
-        StatusCode::OK,
-        Json(res)
-    )
-}
\ No newline at end of file
+    Ok(Json(res?))
+}
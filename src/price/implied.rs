@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::error::app_error::PriceError;
+use crate::handle::base_token::DecimalAmount;
+use crate::handle::handle_tx::SwapItem;
+use crate::price::oracle::{PriceOracle, PriceQuote};
+
+// 隐含价格转换为 DecimalAmount 时保留的精度
+const IMPLIED_PRICE_DECIMALS: u8 = 12;
+
+// 从已解码的 swap 中得到的隐含 USD 价格，按 mint 记录每个时间戳的价格
+// 作为价格来源时返回时间上最接近的一条记录，用于没有预言机的长尾代币
+#[derive(Debug, Clone, Default)]
+pub struct ImpliedPrices {
+    prices: HashMap<Pubkey, Vec<(u64, f64)>>,// 按时间戳升序
+    max_distance: Option<u64>,// 作为价格来源时允许的最大时间差（秒），None 表示不限制
+}
+
+impl ImpliedPrices {
+    // 记录每笔交易中目标代币的隐含价格：与已定价代币之间 swap 的成交价，没有 swap 事件时为整笔交易的成交价
+    pub fn from_items(mint: &Pubkey, items: &[SwapItem]) -> Self {
+        let mut implied = ImpliedPrices::default();
+        for item in items {
+            if let Some(price) = item.implied_price.or_else(|| item.executed_price()) {
+                implied.record(*mint, item.timestamp, price);
+            }
+        }
+        implied
+    }
+
+    pub fn with_max_distance(mut self, max_distance: u64) -> Self {
+        self.max_distance = Some(max_distance);
+        self
+    }
+
+    pub fn record(&mut self, mint: Pubkey, timestamp: u64, price: f64) {
+        if !price.is_finite() || price <= 0.0 {
+            return;
+        }
+        let prices = self.prices.entry(mint).or_default();
+        let index = prices.partition_point(|(recorded, _)| *recorded <= timestamp);
+        prices.insert(index, (timestamp, price));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices.is_empty()
+    }
+
+    // 时间上最接近的隐含价格及其时间差
+    pub fn nearest(&self, mint: &Pubkey, timestamp: u64) -> Option<(u64, f64)> {
+        let prices = self.prices.get(mint)?;
+        let index = prices.partition_point(|(recorded, _)| *recorded < timestamp);
+        let before = index.checked_sub(1).and_then(|i| prices.get(i));
+        let after = prices.get(index);
+        [before, after]
+            .into_iter()
+            .flatten()
+            .map(|(recorded, price)| (recorded.abs_diff(timestamp), *price))
+            .min_by_key(|(distance, _)| *distance)
+    }
+}
+
+#[async_trait]
+impl PriceOracle for ImpliedPrices {
+    fn name(&self) -> &'static str {
+        "implied"
+    }
+
+    async fn historical_price(&self, mint: &Pubkey, timestamp: u64) -> Result<PriceQuote, PriceError> {
+        let (distance, price) = self
+            .nearest(mint, timestamp)
+            .ok_or_else(|| PriceError::UnsupportedMint(mint.to_string()))?;
+        if self.max_distance.is_some_and(|max_distance| distance > max_distance) {
+            return Err(PriceError::NotFound(mint.to_string()));
+        }
+        Ok(PriceQuote::new(DecimalAmount::from_float(price, IMPLIED_PRICE_DECIMALS)))
+    }
+
+    // 隐含价格只反映历史成交，不作为当前价格
+    async fn spot_price(&self, mint: &Pubkey) -> Result<f64, PriceError> {
+        Err(PriceError::UnsupportedMint(mint.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_nearest_implied_price() {
+        let mint = Pubkey::new_unique();
+        let mut implied = ImpliedPrices::default();
+        implied.record(mint, 300, 3.0);
+        implied.record(mint, 100, 1.0);
+        implied.record(mint, 200, 2.0);
+        implied.record(mint, 400, f64::NAN);
+
+        assert_eq!(implied.nearest(&mint, 0), Some((100, 1.0)));
+        assert_eq!(implied.nearest(&mint, 240), Some((40, 2.0)));
+        assert_eq!(implied.nearest(&mint, 1_000), Some((700, 3.0)));
+        assert_eq!(implied.nearest(&Pubkey::new_unique(), 100), None);
+
+        let implied = implied.with_max_distance(60);
+        assert_eq!(implied.historical_price(&mint, 260).await.unwrap().price.to_float(), 3.0);
+        assert!(matches!(implied.historical_price(&mint, 1_000).await, Err(PriceError::NotFound(_))));
+    }
+}
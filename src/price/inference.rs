@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::handle::base_token::DecimalAmount;
+use crate::price::oracle::PriceQuote;
+use crate::tx::inner_tx::SwapInstruction;
+
+// 同一笔交易中与 mint 直接兑换的其他代币
+pub fn counterparts(mint: &Pubkey, swaps: &[SwapInstruction]) -> Vec<Pubkey> {
+    let mut mints = vec![];
+    for swap in swaps {
+        let other = if swap.input_mint == *mint {
+            swap.output_mint
+        } else if swap.output_mint == *mint {
+            swap.input_mint
+        } else {
+            continue;
+        };
+        if other != *mint && !mints.contains(&other) {
+            mints.push(other);
+        }
+    }
+    mints
+}
+
+// 没有价格来源的代币按同一笔交易中它与已定价代币之间的 swap 推算 USD 单价及其置信区间，
+// 例如 BONK→SOL→TOKEN 的路由中按 BONK→SOL 这一跳与 SOL 的价格得到 BONK 的价格；多跳时按数量加权
+pub fn infer_price(
+    mint: &Pubkey,
+    swaps: &[SwapInstruction],
+    decimals: &HashMap<Pubkey, u8>,
+    prices: &HashMap<Pubkey, PriceQuote>,
+) -> Option<(f64, f64)> {
+    let mint_decimals = *decimals.get(mint)?;
+    let mut amount = 0.0;
+    let mut usd_value = 0.0;
+    let mut confidence = 0.0;
+    for swap in swaps {
+        let (mint_amount, other_mint, other_amount) = if swap.input_mint == *mint {
+            (swap.input_amount, swap.output_mint, swap.output_amount)
+        } else if swap.output_mint == *mint {
+            (swap.output_amount, swap.input_mint, swap.input_amount)
+        } else {
+            continue;
+        };
+        let (Some(other_decimals), Some(quote)) = (decimals.get(&other_mint), prices.get(&other_mint)) else {
+            continue;
+        };
+        if other_mint == *mint || mint_amount == 0 {
+            continue;
+        }
+        amount += DecimalAmount::new(mint_amount, mint_decimals).to_float();
+        let other_amount = DecimalAmount::new(other_amount, *other_decimals).to_float();
+        usd_value += other_amount * quote.price.to_float();
+        confidence += other_amount * quote.confidence;
+    }
+    (amount > 0.0).then(|| (usd_value / amount, confidence / amount))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_price_from_route() {
+        let (bonk, sol, token) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        // 1,000,000 BONK → 0.2 SOL → 50 TOKEN
+        let swaps = vec![
+            SwapInstruction { amm: Pubkey::new_unique(), input_mint: bonk, input_amount: 100_000_000_000, output_mint: sol, output_amount: 200_000_000 },
+            SwapInstruction { amm: Pubkey::new_unique(), input_mint: sol, input_amount: 200_000_000, output_mint: token, output_amount: 50_000_000 },
+        ];
+        let decimals = HashMap::from([(bonk, 5), (sol, 9), (token, 6)]);
+        let prices = HashMap::from([(sol, PriceQuote::new(DecimalAmount::new(150_000_000, 6)).with_confidence(0.5))]);
+
+        assert_eq!(counterparts(&bonk, &swaps), vec![sol]);
+        assert_eq!(counterparts(&sol, &swaps), vec![bonk, token]);
+        // 0.2 SOL * 150 / 1,000,000 BONK
+        let (price, confidence) = infer_price(&bonk, &swaps, &decimals, &prices).unwrap();
+        assert!((price - 0.00003).abs() < 1e-12);
+        assert!((confidence - 0.0000001).abs() < 1e-15);
+        // 只与未定价代币兑换时无法推算
+        assert_eq!(infer_price(&token, &swaps, &decimals, &HashMap::new()), None);
+    }
+}
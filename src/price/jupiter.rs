@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::config::app_config::PriceConfig;
+use crate::error::app_error::PriceError;
+use crate::handle::base_token;
+use crate::price::oracle::{PriceOracle, PriceQuote};
+
+// Price API v3 单次请求最多 50 个 id
+const IDS_PER_REQUEST: usize = 50;
+
+// 通过 Jupiter Price API 获取当前价格，该接口没有历史价格
+pub struct JupiterPriceOracle {
+    base_url: String,
+    retries: u32,
+}
+
+impl JupiterPriceOracle {
+    pub fn new(config: &PriceConfig) -> Self {
+        JupiterPriceOracle {
+            base_url: config.jupiter_base_url.clone(),
+            retries: config.retries,
+        }
+    }
+}
+
+#[async_trait]
+impl PriceOracle for JupiterPriceOracle {
+    fn name(&self) -> &'static str {
+        "jupiter"
+    }
+
+    async fn historical_price(&self, mint: &Pubkey, _timestamp: u64) -> Result<PriceQuote, PriceError> {
+        Err(PriceError::UnsupportedMint(mint.to_string()))
+    }
+
+    async fn spot_price(&self, mint: &Pubkey) -> Result<f64, PriceError> {
+        let mint = mint.to_string();
+        let url = format!("{}/price/v3?ids={}", self.base_url, mint);
+        base_token::retry_get_jupiter_price(&mint, &url, self.retries).await
+    }
+
+    // 多个 mint 以逗号分隔合并为一个请求
+    async fn spot_prices(&self, mints: &[Pubkey]) -> HashMap<Pubkey, f64> {
+        let mut prices = HashMap::new();
+        for chunk in mints.chunks(IDS_PER_REQUEST) {
+            let ids = chunk.iter().map(Pubkey::to_string).collect::<Vec<_>>().join(",");
+            let url = format!("{}/price/v3?ids={}", self.base_url, ids);
+            match base_token::retry_get_jupiter_prices(&url, self.retries).await {
+                Ok(found) => prices.extend(chunk.iter().filter_map(|mint| Some((*mint, *found.get(&mint.to_string())?)))),
+                Err(e) => eprintln!("Error fetching jupiter prices for {} mints: {}", chunk.len(), e),
+            }
+        }
+        prices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{extract::Query, routing::get, Json, Router};
+    use serde_json::{json, Value};
+
+    // 本地 Price API：除 unknown 之外的 mint 价格均为 2.5，并记录请求次数
+    async fn serve(unknown: Pubkey, requests: Arc<AtomicUsize>) -> String {
+        let app = Router::new().route("/price/v3", get(move |Query(query): Query<HashMap<String, String>>| async move {
+            requests.fetch_add(1, Ordering::Relaxed);
+            let prices = query["ids"]
+                .split(',')
+                .filter(|id| *id != unknown.to_string())
+                .map(|id| (id.to_string(), json!({ "usdPrice": 2.5 })))
+                .collect::<serde_json::Map<_, _>>();
+            Json(Value::Object(prices))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_batched_spot_prices() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let unknown = Pubkey::new_unique();
+        let config = PriceConfig {
+            jupiter_base_url: serve(unknown, Arc::clone(&requests)).await,
+            ..PriceConfig::default()
+        };
+        let oracle = JupiterPriceOracle::new(&config);
+
+        let mut mints = (0..60).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        mints.push(unknown);
+        let prices = oracle.spot_prices(&mints).await;
+        assert_eq!(prices.len(), 60);
+        assert!(!prices.contains_key(&unknown));
+        assert_eq!(prices[&mints[0]], 2.5);
+        // 61 个 mint 按每批 50 个合并为 2 个请求
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+
+        assert_eq!(oracle.spot_price(&mints[0]).await, Ok(2.5));
+        assert!(matches!(oracle.spot_price(&unknown).await, Err(PriceError::NotFound(_))));
+    }
+}
@@ -0,0 +1,7 @@
+pub mod oracle;
+pub mod pyth;
+pub mod jupiter;
+pub mod static_price;
+pub mod inference;
+pub mod implied;
+pub mod stablecoin;
\ No newline at end of file
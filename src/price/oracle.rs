@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::config::app_config::PriceConfig;
+use crate::error::app_error::PriceError;
+use crate::handle::base_token::DecimalAmount;
+use crate::price::{jupiter::JupiterPriceOracle, pyth::PythPriceOracle, stablecoin::StablecoinPriceOracle};
+
+// 历史价格与其置信区间（±USD），confidence 为 0 表示来源不提供置信区间
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceQuote {
+    pub price: DecimalAmount,
+    pub confidence: f64,
+}
+
+impl PriceQuote {
+    pub fn new(price: DecimalAmount) -> Self {
+        PriceQuote { price, confidence: 0.0 }
+    }
+
+    pub fn with_confidence(mut self, confidence: f64) -> Self {
+        self.confidence = confidence;
+        self
+    }
+}
+
+// 价格来源：historical_price 为某一时间戳的 USD 价格，spot_price 为当前 USD 价格
+// 不支持的 mint 返回 PriceError::UnsupportedMint，组合使用时交给下一个来源
+#[async_trait]
+pub trait PriceOracle: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn historical_price(&self, mint: &Pubkey, timestamp: u64) -> Result<PriceQuote, PriceError>;
+
+    async fn spot_price(&self, mint: &Pubkey) -> Result<f64, PriceError>;
+
+    // 批量查询同一时间戳的价格，只返回获取成功的 mint；默认逐个查询
+    async fn historical_prices(&self, mints: &[Pubkey], timestamp: u64) -> HashMap<Pubkey, PriceQuote> {
+        let mut prices = HashMap::new();
+        for mint in mints {
+            if let Ok(price) = self.historical_price(mint, timestamp).await {
+                prices.insert(*mint, price);
+            }
+        }
+        prices
+    }
+
+    async fn spot_prices(&self, mints: &[Pubkey]) -> HashMap<Pubkey, f64> {
+        let mut prices = HashMap::new();
+        for mint in mints {
+            if let Ok(price) = self.spot_price(mint).await {
+                prices.insert(*mint, price);
+            }
+        }
+        prices
+    }
+}
+
+// 按优先级组合多个价格来源，依次尝试直到某个来源返回价格
+pub struct PriceOracleChain {
+    oracles: Vec<Arc<dyn PriceOracle>>,
+}
+
+impl PriceOracleChain {
+    pub fn new(oracles: Vec<Arc<dyn PriceOracle>>) -> Self {
+        PriceOracleChain { oracles }
+    }
+
+    // 默认顺序：稳定币（锚定时按 1 USD 计）、Pyth 历史价格、Jupiter 当前价格
+    // 稳定币的 Pyth feed 已由稳定币来源请求过，失败时不再重复请求同一个 feed
+    pub fn from_config(config: &PriceConfig) -> Self {
+        let stablecoins = config.stablecoins.iter().filter_map(|mint| mint.parse::<Pubkey>().ok());
+        PriceOracleChain::new(vec![
+            Arc::new(StablecoinPriceOracle::from_config(config)),
+            Arc::new(PythPriceOracle::new(config).excluding(stablecoins)),
+            Arc::new(JupiterPriceOracle::new(config)),
+        ])
+    }
+}
+
+#[async_trait]
+impl PriceOracle for PriceOracleChain {
+    fn name(&self) -> &'static str {
+        "chain"
+    }
+
+    // 返回第一个成功的价格；都失败时返回最后一个请求错误，全部不支持时返回 UnsupportedMint
+    async fn historical_price(&self, mint: &Pubkey, timestamp: u64) -> Result<PriceQuote, PriceError> {
+        let mut last_error = PriceError::UnsupportedMint(mint.to_string());
+        for oracle in &self.oracles {
+            match oracle.historical_price(mint, timestamp).await {
+                Ok(price) => return Ok(price),
+                Err(PriceError::UnsupportedMint(_)) => {},
+                Err(e) => {
+                    #[cfg(test)]
+                    println!("{} failed to price {}: {}", oracle.name(), mint, e);
+
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn spot_price(&self, mint: &Pubkey) -> Result<f64, PriceError> {
+        let mut last_error = PriceError::UnsupportedMint(mint.to_string());
+        for oracle in &self.oracles {
+            match oracle.spot_price(mint).await {
+                Ok(price) => return Ok(price),
+                Err(PriceError::UnsupportedMint(_)) => {},
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    // 前一个来源没有返回价格的 mint 交给下一个来源
+    async fn historical_prices(&self, mints: &[Pubkey], timestamp: u64) -> HashMap<Pubkey, PriceQuote> {
+        let mut prices = HashMap::new();
+        let mut remaining = mints.to_vec();
+        for oracle in &self.oracles {
+            if remaining.is_empty() {
+                break;
+            }
+            let found = oracle.historical_prices(&remaining, timestamp).await;
+            remaining.retain(|mint| !found.contains_key(mint));
+            prices.extend(found);
+        }
+        prices
+    }
+
+    async fn spot_prices(&self, mints: &[Pubkey]) -> HashMap<Pubkey, f64> {
+        let mut prices = HashMap::new();
+        let mut remaining = mints.to_vec();
+        for oracle in &self.oracles {
+            if remaining.is_empty() {
+                break;
+            }
+            let found = oracle.spot_prices(&remaining).await;
+            remaining.retain(|mint| !found.contains_key(mint));
+            prices.extend(found);
+        }
+        prices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price::static_price::StaticPriceOracle;
+
+    // 对所有 mint 都返回请求失败的来源
+    struct Unavailable;
+
+    #[async_trait]
+    impl PriceOracle for Unavailable {
+        fn name(&self) -> &'static str {
+            "unavailable"
+        }
+
+        async fn historical_price(&self, _mint: &Pubkey, _timestamp: u64) -> Result<PriceQuote, PriceError> {
+            Err(PriceError::Request("connection refused".to_string()))
+        }
+
+        async fn spot_price(&self, _mint: &Pubkey) -> Result<f64, PriceError> {
+            Err(PriceError::Request("connection refused".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_priority() {
+        let (first, second, unknown) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let chain = PriceOracleChain::new(vec![
+            Arc::new(StaticPriceOracle::new(HashMap::from([(first, DecimalAmount::new(2_000_000, 6))]))),
+            Arc::new(Unavailable),
+            Arc::new(StaticPriceOracle::new(HashMap::from([
+                (first, DecimalAmount::new(3_000_000, 6)),
+                (second, DecimalAmount::new(5_000_000, 6)),
+            ]))),
+        ]);
+
+        assert_eq!(chain.historical_price(&first, 0).await.unwrap().price.to_float(), 2.0);
+        assert_eq!(chain.historical_price(&second, 0).await.unwrap().price.to_float(), 5.0);
+        assert_eq!(chain.spot_price(&second).await, Ok(5.0));
+        // 没有来源支持时返回中间来源的请求错误
+        assert_eq!(chain.historical_price(&unknown, 0).await, Err(PriceError::Request("connection refused".to_string())));
+
+        let prices = chain.historical_prices(&[first, second, unknown], 0).await;
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices[&second].price.to_float(), 5.0);
+    }
+
+    // 本地 Hermes：所有 feed 只有 1970 年的价格，并记录请求次数
+    async fn serve_stale_hermes(requests: Arc<std::sync::atomic::AtomicUsize>) -> String {
+        use axum::{routing::get, Json, Router};
+
+        let app = Router::new().route("/v2/updates/price/{timestamp}", get(move || async move {
+            requests.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Json(serde_json::json!({
+                "parsed": [{ "price": { "price": "100000000", "conf": "10000", "expo": -8, "publish_time": 0 } }]
+            }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_stablecoin_feed_is_not_requested_twice() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use crate::handle::base_token::{USDC, WSOL};
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let config = PriceConfig {
+            pyth_base_url: serve_stale_hermes(Arc::clone(&requests)).await,
+            ..PriceConfig::default()
+        };
+        let chain = PriceOracleChain::from_config(&config);
+
+        // 稳定币 feed 过旧时只请求一次，错误原样返回
+        let price = chain.historical_price(&Pubkey::from_str_const(USDC), 1_000_000).await;
+        assert!(matches!(price, Err(PriceError::Stale(_, 1_000_000))));
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+
+        // 其他 mint 仍由 Pyth 定价
+        let price = chain.historical_price(&Pubkey::from_str_const(WSOL), 1_000_000).await;
+        assert!(matches!(price, Err(PriceError::Stale(_, 1_000_000))));
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+    }
+}
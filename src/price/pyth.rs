@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::config::app_config::PriceConfig;
+use crate::error::app_error::PriceError;
+use crate::handle::base_token::{self, PYTH_SOL_USD_PRICE_FEED_ID, USDC, USDT, WSOL};
+use crate::price::oracle::{PriceOracle, PriceQuote};
+
+// 内置的 mint -> Pyth price feed id 映射，可通过配置 pythFeeds 补充或覆盖
+const PYTH_FEEDS: [(&str, &str); 7] = [
+    (WSOL, PYTH_SOL_USD_PRICE_FEED_ID),
+    (USDC, "0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a"),
+    (USDT, "0x2b89b9dc8fdf9f34709a5b106b472f0f39bb6ca9ce04b0fd7f2e971688e2e53b"),
+    ("JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN", "0x0a0408d619e9380abad35060f9192039ed5042fa6f82301d0e48bb52be830996"), // JUP
+    ("DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263", "0x72b021217ca3fe68922a19aaf990109cb9d84e9ad004b4d2025ad6f529314419"), // BONK
+    ("EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm", "0x4ca4beeca86f0d164160323817a4e42b10010a724c2217c6ee41b54cd4cc61fc"), // WIF
+    ("jtojtomepa8beP8AuQc6eXt5FriJwfFMwQx2v2f9mCL", "0xb43660a5f790c69354b0729a5ef9d50d68f1df92107540210b9cccba1f947cc2"), // JTO
+];
+
+// 通过 Pyth Hermes 获取历史价格与当前价格，只支持有对应 price feed 的 mint
+pub struct PythPriceOracle {
+    base_url: String,
+    retries: u32,
+    max_staleness: u64,
+    feeds: HashMap<Pubkey, String>,
+}
+
+impl PythPriceOracle {
+    pub fn new(config: &PriceConfig) -> Self {
+        let mut feeds = PYTH_FEEDS
+            .iter()
+            .map(|(mint, feed_id)| (Pubkey::from_str_const(mint), feed_id.to_string()))
+            .collect::<HashMap<_, _>>();
+        // Config::load 时已校验过 mint 与 feed id 的格式
+        for (mint, feed_id) in &config.pyth_feeds {
+            if let Ok(mint) = mint.parse::<Pubkey>() {
+                feeds.insert(mint, normalize_feed_id(feed_id));
+            }
+        }
+        PythPriceOracle {
+            base_url: config.pyth_base_url.clone(),
+            retries: config.retries,
+            max_staleness: config.pyth_max_staleness_secs,
+            feeds,
+        }
+    }
+
+    // 不再为这些 mint 提供价格（返回 UnsupportedMint）
+    pub fn excluding(mut self, mints: impl IntoIterator<Item = Pubkey>) -> Self {
+        for mint in mints {
+            self.feeds.remove(&mint);
+        }
+        self
+    }
+
+    fn feed_id(&self, mint: &Pubkey) -> Result<&str, PriceError> {
+        self.feeds
+            .get(mint)
+            .map(String::as_str)
+            .ok_or_else(|| PriceError::UnsupportedMint(mint.to_string()))
+    }
+}
+
+pub fn has_builtin_feed(mint: &str) -> bool {
+    PYTH_FEEDS.iter().any(|(feed_mint, _)| *feed_mint == mint)
+}
+
+// Hermes 接受带或不带 0x 前缀的 feed id，统一为带前缀的小写形式
+fn normalize_feed_id(feed_id: &str) -> String {
+    format!("0x{}", feed_id.trim_start_matches("0x").to_lowercase())
+}
+
+#[async_trait]
+impl PriceOracle for PythPriceOracle {
+    fn name(&self) -> &'static str {
+        "pyth"
+    }
+
+    // Hermes 返回该时间戳之后的第一个价格更新，发布时间与交易时间相差过大（如 feed 当时尚未上线或暂停更新）时不使用
+    async fn historical_price(&self, mint: &Pubkey, timestamp: u64) -> Result<PriceQuote, PriceError> {
+        let url = format!("{}/v2/updates/price/{}?ids%5B%5D={}", self.base_url, timestamp, self.feed_id(mint)?);
+        let price = base_token::retry(&url, self.retries).await?;
+        let distance = price.publish_time.abs_diff(i64::try_from(timestamp).unwrap_or(i64::MAX));
+        if distance > self.max_staleness {
+            return Err(PriceError::Stale(mint.to_string(), distance));
+        }
+        Ok(PriceQuote::new(price.to_decimal_amount()?).with_confidence(price.confidence()))
+    }
+
+    async fn spot_price(&self, mint: &Pubkey) -> Result<f64, PriceError> {
+        let url = format!("{}/v2/updates/price/latest?ids%5B%5D={}", self.base_url, self.feed_id(mint)?);
+        let price = base_token::retry(&url, self.retries).await?;
+        Ok(price.to_decimal_amount()?.to_float())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feed_registry() {
+        let jup = "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN";
+        let custom = Pubkey::new_unique();
+        let config = PriceConfig {
+            pyth_feeds: HashMap::from([
+                (custom.to_string(), "ABCDEF".repeat(10) + "abcd"),
+                (jup.to_string(), "0x".to_string() + &"1".repeat(64)),
+            ]),
+            ..PriceConfig::default()
+        };
+        let oracle = PythPriceOracle::new(&config);
+
+        assert_eq!(oracle.feed_id(&Pubkey::from_str_const(WSOL)), Ok(PYTH_SOL_USD_PRICE_FEED_ID));
+        assert_eq!(oracle.feed_id(&custom), Ok(format!("0x{}abcd", "abcdef".repeat(10)).as_str()));
+        // 配置覆盖内置映射
+        assert_eq!(oracle.feed_id(&Pubkey::from_str_const(jup)), Ok(format!("0x{}", "1".repeat(64)).as_str()));
+        assert!(matches!(oracle.feed_id(&Pubkey::new_unique()), Err(PriceError::UnsupportedMint(_))));
+    }
+}
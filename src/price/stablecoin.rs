@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::config::app_config::PriceConfig;
+use crate::error::app_error::PriceError;
+use crate::handle::base_token::DecimalAmount;
+use crate::price::oracle::{PriceOracle, PriceQuote};
+use crate::price::pyth::PythPriceOracle;
+
+// 稳定币按其自身的价格 feed 估值：价格在锚定容差内时按 1 USD 计，并认为同一时间段内都保持锚定，
+// 不再请求；脱锚时使用 feed 在交易时间的实际价格
+pub struct StablecoinPriceOracle {
+    feed: Box<dyn PriceOracle>,
+    mints: HashSet<Pubkey>,
+    peg_tolerance: f64,// 与 1 USD 的最大相对偏差，为 0 时每次都使用 feed 的实际价格
+    peg_check_interval: u64,// 一次确认锚定后，同一时间段（秒）内的价格直接按 1 USD 计
+    cache: Mutex<StablecoinCache>,
+}
+
+// 缓存在进程内长期存在，每张表超过该数量时整体清空
+const MAX_CACHE_ENTRIES: usize = 100_000;
+
+#[derive(Default)]
+struct StablecoinCache {
+    pegged: HashSet<(Pubkey, u64)>,// 已确认锚定的 (mint, 时间段)
+    depegged: HashMap<(Pubkey, u64), PriceQuote>,// 脱锚期间按具体时间戳缓存
+}
+
+impl StablecoinCache {
+    fn insert_pegged(&mut self, period: (Pubkey, u64)) {
+        if self.pegged.len() >= MAX_CACHE_ENTRIES {
+            self.pegged.clear();
+        }
+        self.pegged.insert(period);
+    }
+
+    fn insert_depegged(&mut self, key: (Pubkey, u64), quote: PriceQuote) {
+        if self.depegged.len() >= MAX_CACHE_ENTRIES {
+            self.depegged.clear();
+        }
+        self.depegged.insert(key, quote);
+    }
+}
+
+impl StablecoinPriceOracle {
+    pub fn new(feed: Box<dyn PriceOracle>, mints: HashSet<Pubkey>, peg_tolerance: f64, peg_check_interval: u64) -> Self {
+        StablecoinPriceOracle {
+            feed,
+            mints,
+            peg_tolerance,
+            peg_check_interval: peg_check_interval.max(1),
+            cache: Mutex::new(StablecoinCache::default()),
+        }
+    }
+
+    // Config::load 时已校验过 mint 的格式
+    pub fn from_config(config: &PriceConfig) -> Self {
+        let mints = config.stablecoins.iter().filter_map(|mint| mint.parse().ok()).collect();
+        StablecoinPriceOracle::new(
+            Box::new(PythPriceOracle::new(config)),
+            mints,
+            config.peg_tolerance,
+            config.peg_check_interval_secs,
+        )
+    }
+
+    fn is_pegged(&self, price: f64) -> bool {
+        (price - 1.0).abs() <= self.peg_tolerance
+    }
+
+    fn peg() -> PriceQuote {
+        PriceQuote::new(DecimalAmount::new(1_000_000, 6))
+    }
+}
+
+#[async_trait]
+impl PriceOracle for StablecoinPriceOracle {
+    fn name(&self) -> &'static str {
+        "stablecoin"
+    }
+
+    async fn historical_price(&self, mint: &Pubkey, timestamp: u64) -> Result<PriceQuote, PriceError> {
+        if !self.mints.contains(mint) {
+            return Err(PriceError::UnsupportedMint(mint.to_string()));
+        }
+        let period = (*mint, timestamp / self.peg_check_interval);
+        {
+            let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            if self.peg_tolerance > 0.0 && cache.pegged.contains(&period) {
+                return Ok(Self::peg());
+            }
+            if let Some(quote) = cache.depegged.get(&(*mint, timestamp)) {
+                return Ok(quote.clone());
+            }
+        }
+
+        // feed 不可用（价格过旧、请求失败等）时不假定锚定，交给链上后续的 oracle，都失败时该笔交易计入未定价
+        let quote = self.feed.historical_price(mint, timestamp).await?;
+
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if self.peg_tolerance > 0.0 && self.is_pegged(quote.price.to_float()) {
+            cache.insert_pegged(period);
+            return Ok(Self::peg());
+        }
+        cache.insert_depegged((*mint, timestamp), quote.clone());
+        Ok(quote)
+    }
+
+    async fn spot_price(&self, mint: &Pubkey) -> Result<f64, PriceError> {
+        if !self.mints.contains(mint) {
+            return Err(PriceError::UnsupportedMint(mint.to_string()));
+        }
+        let price = self.feed.spot_price(mint).await?;
+        if self.peg_tolerance > 0.0 && self.is_pegged(price) {
+            return Ok(1.0);
+        }
+        Ok(price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // 按时间戳返回价格的 feed：1000 之前锚定在 0.999，之后脱锚到 0.88，5000 之后价格过旧，并记录请求次数
+    struct Feed {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl PriceOracle for Feed {
+        fn name(&self) -> &'static str {
+            "feed"
+        }
+
+        async fn historical_price(&self, _mint: &Pubkey, timestamp: u64) -> Result<PriceQuote, PriceError> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            match timestamp {
+                ..1_000 => Ok(PriceQuote::new(DecimalAmount::new(99_900, 5))),
+                1_000..5_000 => Ok(PriceQuote::new(DecimalAmount::new(88_000, 5)).with_confidence(0.01)),
+                _ => Err(PriceError::Stale("feed".to_string(), 1_000)),
+            }
+        }
+
+        async fn spot_price(&self, _mint: &Pubkey) -> Result<f64, PriceError> {
+            Ok(0.998)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_depeg_aware_pricing() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let usdc = Pubkey::new_unique();
+        let oracle = StablecoinPriceOracle::new(Box::new(Feed { calls: Arc::clone(&calls) }), HashSet::from([usdc]), 0.005, 600);
+
+        // 锚定：按 1 USD 计，同一时间段内不再请求
+        assert_eq!(oracle.historical_price(&usdc, 100).await.unwrap(), StablecoinPriceOracle::peg());
+        assert_eq!(oracle.historical_price(&usdc, 500).await.unwrap(), StablecoinPriceOracle::peg());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // 脱锚：使用 feed 的实际价格，每个时间戳单独请求
+        let quote = oracle.historical_price(&usdc, 1_200).await.unwrap();
+        assert_eq!(quote.price.to_float(), 0.88);
+        assert_eq!(quote.confidence, 0.01);
+        oracle.historical_price(&usdc, 1_200).await.unwrap();
+        oracle.historical_price(&usdc, 1_201).await.unwrap();
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+        // feed 不可用时返回错误，不假定锚定
+        assert!(matches!(oracle.historical_price(&usdc, 5_000).await, Err(PriceError::Stale(_, 1_000))));
+        assert_eq!(oracle.spot_price(&usdc).await, Ok(1.0));
+        assert!(matches!(oracle.historical_price(&Pubkey::new_unique(), 100).await, Err(PriceError::UnsupportedMint(_))));
+
+        // 容差为 0 时总是使用 feed 的价格
+        let oracle = StablecoinPriceOracle::new(Box::new(Feed { calls: Arc::clone(&calls) }), HashSet::from([usdc]), 0.0, 600);
+        assert_eq!(oracle.historical_price(&usdc, 100).await.unwrap().price.to_float(), 0.999);
+        assert_eq!(oracle.spot_price(&usdc).await, Ok(0.998));
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let usdc = Pubkey::new_unique();
+        let mut cache = StablecoinCache::default();
+        for timestamp in 0..MAX_CACHE_ENTRIES as u64 + 10 {
+            cache.insert_pegged((usdc, timestamp));
+            cache.insert_depegged((usdc, timestamp), StablecoinPriceOracle::peg());
+        }
+        assert_eq!(cache.pegged.len(), 10);
+        assert_eq!(cache.depegged.len(), 10);
+    }
+}
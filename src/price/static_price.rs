@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::error::app_error::PriceError;
+use crate::handle::base_token::DecimalAmount;
+use crate::price::oracle::{PriceOracle, PriceQuote};
+
+// 固定价格，不随时间变化（如测试或手动指定的价格）
+pub struct StaticPriceOracle {
+    prices: HashMap<Pubkey, DecimalAmount>,
+}
+
+impl StaticPriceOracle {
+    pub fn new(prices: HashMap<Pubkey, DecimalAmount>) -> Self {
+        StaticPriceOracle { prices }
+    }
+}
+
+#[async_trait]
+impl PriceOracle for StaticPriceOracle {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    async fn historical_price(&self, mint: &Pubkey, _timestamp: u64) -> Result<PriceQuote, PriceError> {
+        self.prices
+            .get(mint)
+            .cloned()
+            .map(PriceQuote::new)
+            .ok_or_else(|| PriceError::UnsupportedMint(mint.to_string()))
+    }
+
+    async fn spot_price(&self, mint: &Pubkey) -> Result<f64, PriceError> {
+        self.historical_price(mint, 0).await.map(|quote| quote.price.to_float())
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedTransaction, EncodedTransactionWithStatusMeta, UiMessage,
+    UiTransactionTokenBalance,
+};
+
+use crate::handle::base_token::WSOL;
+use crate::handle::route::{Leg, NetTrade};
+use crate::tx::network_fee;
+
+const WSOL_DECIMALS: u8 = 9;
+
+// 用户在一笔交易中某个 mint 的净变化（最小单位），正数为净流入
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceDelta {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub delta: i128,
+}
+
+// 交易涉及的全部账户（JsonParsed 编码下包含地址查找表加载的账户），顺序与 pre/post balances 一致
+pub fn account_keys(transaction: &EncodedTransaction) -> Vec<Pubkey> {
+    let EncodedTransaction::Json(transaction) = transaction else {
+        return vec![];
+    };
+    match &transaction.message {
+        UiMessage::Parsed(message) => message.account_keys
+            .iter()
+            .filter_map(|account| account.pubkey.parse().ok())
+            .collect(),
+        UiMessage::Raw(message) => message.account_keys
+            .iter()
+            .filter_map(|key| key.parse().ok())
+            .collect(),
+    }
+}
+
+// 交易的签名者，非 JsonParsed 编码时无法得知，返回 None
+pub fn signers(transaction: &EncodedTransaction) -> Option<Vec<Pubkey>> {
+    let EncodedTransaction::Json(transaction) = transaction else {
+        return None;
+    };
+    match &transaction.message {
+        UiMessage::Parsed(message) => Some(message.account_keys
+            .iter()
+            .filter(|account| account.signer)
+            .filter_map(|account| account.pubkey.parse().ok())
+            .collect()),
+        UiMessage::Raw(message) => Some(message.account_keys
+            .iter()
+            .take(usize::from(message.header.num_required_signatures))
+            .filter_map(|key| key.parse().ok())
+            .collect()),
+    }
+}
+
+// 对比 pre/post token balances 以及 lamports，得到用户在该交易中各 mint 的净变化
+// SOL 与 WSOL 合并计算：钱包与用户所有代币账户的 lamports 变化之和（加回网络费用），
+// 这样 ATA 创建/关闭产生的租金在钱包与代币账户之间相互抵消，WSOL 的包装/解包也不会重复计算
+pub fn balance_deltas(
+    user: &Pubkey,
+    transaction: &EncodedTransactionWithStatusMeta,
+) -> Vec<BalanceDelta> {
+    let Some(meta) = transaction.meta.as_ref() else {
+        return vec![];
+    };
+    let user_str = user.to_string();
+    let owned = |balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>| match balances {
+        OptionSerializer::Some(balances) => balances
+            .iter()
+            .filter(|balance| matches!(balance.owner.as_ref(), OptionSerializer::Some(owner) if *owner == user_str))
+            .cloned()
+            .collect::<Vec<_>>(),
+        _ => vec![],
+    };
+    let pre = owned(&meta.pre_token_balances);
+    let post = owned(&meta.post_token_balances);
+
+    let mut deltas: Vec<BalanceDelta> = vec![];
+    let mut add = |mint: Pubkey, decimals: u8, delta: i128| {
+        match deltas.iter_mut().find(|d| d.mint == mint) {
+            Some(d) => d.delta += delta,
+            None => deltas.push(BalanceDelta { mint, decimals, delta }),
+        }
+    };
+
+    let wsol = Pubkey::from_str_const(WSOL);
+    for (balances, sign) in [(&pre, -1), (&post, 1)] {
+        for balance in balances {
+            let Ok(mint) = balance.mint.parse::<Pubkey>() else {
+                continue;
+            };
+            if mint == wsol {
+                continue; // 由 lamports 计算
+            }
+            let amount = balance.ui_token_amount.amount.parse::<u64>().unwrap_or(0);
+            add(mint, balance.ui_token_amount.decimals, sign * i128::from(amount));
+        }
+    }
+
+    let keys = account_keys(&transaction.transaction);
+    let mut lamport_accounts = pre
+        .iter()
+        .chain(post.iter())
+        .map(|balance| balance.account_index as usize)
+        .collect::<HashSet<_>>();
+    if let Some(index) = keys.iter().position(|key| key == user) {
+        lamport_accounts.insert(index);
+    }
+    let lamports = lamport_accounts
+        .into_iter()
+        .filter_map(|index| {
+            let pre = *meta.pre_balances.get(index)?;
+            let post = *meta.post_balances.get(index)?;
+            Some(i128::from(post) - i128::from(pre))
+        })
+        .sum::<i128>();
+    // 网络费用单独统计，不作为交易的一部分
+    let lamports = lamports + i128::from(network_fee::paid_fee(user, transaction));
+    add(wsol, WSOL_DECIMALS, lamports);
+
+    deltas.retain(|d| d.delta != 0);
+    deltas
+}
+
+// 将余额变化转换为净交易，用于没有 swap 事件的交易
+pub fn net_trade(deltas: &[BalanceDelta]) -> NetTrade {
+    let mut trade = NetTrade::default();
+    for delta in deltas {
+        let amount = u64::try_from(delta.delta.unsigned_abs()).unwrap_or(u64::MAX);
+        if delta.delta > 0 {
+            trade.outputs.push(Leg { mint: delta.mint, amount });
+        } else if delta.delta < 0 {
+            trade.inputs.push(Leg { mint: delta.mint, amount });
+        }
+    }
+    trade
+}
+
+// 事件推算的目标 mint 净变化与余额变化是否一致（误差 0.1% 以内）
+// 以目标 mint 收取的平台费用无论从输入还是输出中扣除，都会使实际余额变化比事件少 fee_amount
+pub fn cross_check(trade: &NetTrade, fee_amount: u64, deltas: &[BalanceDelta], mint: &Pubkey) -> bool {
+    let expected = trade.net_amount(mint) - i128::from(fee_amount);
+    let actual = deltas.iter().find(|d| d.mint == *mint).map_or(0, |d| d.delta);
+    (expected - actual).abs() <= (actual.abs() / 1000).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "J14Cg556roeBSgWFEKNTiSQeydMPRW6FZNB2zDMmSadQ";
+    const MINT: &str = "KMNo3nJsBXfcpJTVhZcXLW7RmTwTt4GVFE7suUBo9sS";
+    const USER_ATA: &str = "8Hh1pNdfBmhwa7dqYrJY9mDn2MfwAzwZKmGiDDD8mpaK";
+    const WSOL_ATA: &str = "3ZvSkEAhCgbVeXGhXwc4UCVkjrcQwQnNuBgnNRVTyWXj";
+
+    fn token_balance(index: u8, mint: &str, amount: u64) -> serde_json::Value {
+        serde_json::json!({
+            "accountIndex": index,
+            "mint": mint,
+            "owner": USER,
+            "uiTokenAmount": {
+                "uiAmount": null,
+                "decimals": 6,
+                "amount": amount.to_string(),
+                "uiAmountString": ""
+            }
+        })
+    }
+
+    // 用 SOL 买入 MINT：本交易中创建 MINT 的 ATA（租金 2039280），WSOL ATA 创建后又关闭
+    fn transaction() -> EncodedTransactionWithStatusMeta {
+        let key = |pubkey: &str| serde_json::json!({ "pubkey": pubkey, "writable": true, "signer": false, "source": "transaction" });
+        serde_json::from_value(serde_json::json!({
+            "transaction": {
+                "signatures": [],
+                "message": {
+                    "accountKeys": [key(USER), key(USER_ATA), key(WSOL_ATA)],
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": []
+                }
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [10_000_000_000u64, 0, 0],
+                "postBalances": [10_000_000_000u64 - 5000 - 2_039_280 - 1_000_000_000, 2_039_280, 0],
+                "preTokenBalances": [],
+                "postTokenBalances": [token_balance(1, MINT, 123_000_000)]
+            }
+        }))
+        .expect("Failed to build transaction")
+    }
+
+    #[test]
+    fn test_balance_deltas() {
+        let user = Pubkey::from_str_const(USER);
+        let deltas = balance_deltas(&user, &transaction());
+        assert_eq!(deltas, vec![
+            BalanceDelta { mint: Pubkey::from_str_const(MINT), decimals: 6, delta: 123_000_000 },
+            // 租金与网络费用都不计入
+            BalanceDelta { mint: Pubkey::from_str_const(WSOL), decimals: 9, delta: -1_000_000_000 },
+        ]);
+
+        let trade = net_trade(&deltas);
+        assert_eq!(trade.net_amount(&Pubkey::from_str_const(MINT)), 123_000_000);
+        assert_eq!(trade.inputs, vec![Leg { mint: Pubkey::from_str_const(WSOL), amount: 1_000_000_000 }]);
+    }
+
+    #[test]
+    fn test_cross_check() {
+        let user = Pubkey::from_str_const(USER);
+        let mint = Pubkey::from_str_const(MINT);
+        let deltas = balance_deltas(&user, &transaction());
+        let trade = |amount| NetTrade {
+            inputs: vec![],
+            outputs: vec![Leg { mint, amount }],
+        };
+        assert!(cross_check(&trade(123_000_000), 0, &deltas, &mint));
+        // 事件中的输出包含以该 mint 收取的平台费用
+        assert!(cross_check(&trade(123_500_000), 500_000, &deltas, &mint));
+        assert!(!cross_check(&trade(130_000_000), 0, &deltas, &mint));
+    }
+}
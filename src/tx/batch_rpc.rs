@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+
+use crate::tx::rpc_middleware::{RpcMiddleware, RpcMiddlewareError};
+
+// 批量请求中单个 getTransaction 的结果：Err 表示该条需要回退到单笔请求
+pub type BatchItem = Result<EncodedConfirmedTransactionWithStatusMeta, String>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchError {
+    Rejected(String),// 节点明确不支持 batch，后续应直接使用单笔请求
+    Transport(String),// 网络等临时错误，仅本次回退
+}
+
+// 通过 JSON-RPC batch（一个 HTTP 请求携带 N 个 getTransaction）获取交易
+#[derive(Clone)]
+pub struct BatchRpcClient {
+    middleware: RpcMiddleware,
+}
+
+impl BatchRpcClient {
+    pub fn new(middleware: RpcMiddleware) -> Self {
+        BatchRpcClient {
+            middleware,
+        }
+    }
+
+    // 返回 Err 表示整个 batch 失败（部分 RPC 提供商不支持 batch），调用方应改用单笔请求
+    pub async fn get_transactions(
+        &self,
+        signatures: &[Signature],
+        config: RpcTransactionConfig,
+    ) -> Result<Vec<(Signature, BatchItem)>, BatchError> {
+        let payload = signatures
+            .iter()
+            .enumerate()
+            .map(|(id, sig)| json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "getTransaction",
+                "params": [sig.to_string(), config],
+            }))
+            .collect::<Vec<_>>();
+
+        // 限流、退避与 429 处理由中间件完成；重试耗尽视为临时错误，其余视为节点拒绝 batch
+        let body = self.middleware
+            .send_json(&Value::Array(payload))
+            .await
+            .map_err(|e| match e {
+                RpcMiddlewareError::Exhausted(_) => BatchError::Transport(e.to_string()),
+                RpcMiddlewareError::Permanent { .. } => BatchError::Rejected(e.to_string()),
+            })?;
+
+        parse_batch_response(signatures, body)
+    }
+}
+
+pub fn parse_batch_response(
+    signatures: &[Signature],
+    body: Value,
+) -> Result<Vec<(Signature, BatchItem)>, BatchError> {
+    // 不支持 batch 的节点通常返回单个 error 对象而不是数组
+    let Value::Array(items) = body else {
+        return Err(BatchError::Rejected(format!("Batch not supported: {}", body)));
+    };
+
+    let mut by_id = items
+        .into_iter()
+        .filter_map(|item| {
+            let id = item.get("id")?.as_u64()? as usize;
+            Some((id, item))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let results = signatures
+        .iter()
+        .enumerate()
+        .map(|(id, sig)| {
+            let item = match by_id.remove(&id) {
+                Some(mut item) => match (item.get_mut("result").map(Value::take), item.get("error")) {
+                    (_, Some(error)) => Err(format!("RPC error: {}", error)),
+                    (Some(Value::Null), _) | (None, _) => Err("Transaction not found".to_string()),
+                    (Some(result), _) => serde_json::from_value::<EncodedConfirmedTransactionWithStatusMeta>(result)
+                        .map_err(|e| format!("Invalid transaction: {}", e)),
+                },
+                None => Err("Missing response item".to_string()),
+            };
+            (*sig, item)
+        })
+        .collect();
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_batch_response() {
+        let signatures = vec![
+            Signature::from([1u8; 64]),
+            Signature::from([2u8; 64]),
+            Signature::from([3u8; 64]),
+        ];
+        // 返回顺序与请求顺序不一致，且缺少 id 为 2 的结果
+        let body = json!([
+            { "jsonrpc": "2.0", "id": 1, "error": { "code": -32005, "message": "Node is behind" } },
+            { "jsonrpc": "2.0", "id": 0, "result": null },
+        ]);
+
+        let results = parse_batch_response(&signatures, body).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0, signatures[0]);
+        assert_eq!(results[0].1, Err("Transaction not found".to_string()));
+        assert!(results[1].1.as_ref().unwrap_err().starts_with("RPC error"));
+        assert_eq!(results[2].1, Err("Missing response item".to_string()));
+    }
+
+    #[test]
+    fn test_batch_rejected() {
+        let signatures = vec![Signature::from([1u8; 64])];
+        let body = json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32600, "message": "Batch requests are not supported" } });
+        assert!(matches!(parse_batch_response(&signatures, body), Err(BatchError::Rejected(_))));
+    }
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+// 本次统计的数据覆盖情况，用于让调用方判断 PnL 是否只基于部分交易
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Coverage {
+    pub signatures_discovered: usize,
+    pub transactions_fetched: usize,
+    pub fetch_failed: usize,
+    pub failed_signatures: Vec<String>,// 获取失败的签名，调用方可据此重试
+    pub missing_meta: usize,// 交易缺少 meta，无法分析
+    pub skipped_unrelated: usize,// owner 钱包中与该 mint 无关的交易
+    pub skipped_non_swap: usize,// 涉及该 mint 但既不是交易也不是转账的交易（开关账户、DCA/限价单存取等）
+    pub transfers: usize,// 该 mint 的非交易转入转出
+    pub parse_failures: usize,// discriminator 匹配但无法解码的事件数量
+    pub unknown_events: usize,// 未识别的 Jupiter 事件类型数量，不影响完整性
+    pub delta_mismatches: usize,// swap 事件与余额变化不一致的交易数量
+    pub delta_fallbacks: usize,// 没有 swap 事件、由余额变化推算交易的数量
+    pub transfer_fee_approximate: usize,// Token-2022 转账费率已被覆盖且无法由余额变化推算、按近似费率扣除的买入数量
+    pub implied_priced: usize,// 对手方无法定价、按相邻交易的隐含价格估值的交易数量
+    pub unpriced: usize,// 所有价格来源（包括隐含价格）都无法定价、未计入 PnL 的交易数量
+}
+
+impl Coverage {
+    // 所有发现的签名都成功获取并解析，且所有交易都已定价
+    pub fn is_complete(&self) -> bool {
+        self.fetch_failed == 0 && self.missing_meta == 0 && self.parse_failures == 0 && self.unpriced == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unpriced_is_incomplete() {
+        assert!(Coverage::default().is_complete());
+        let coverage = Coverage { unpriced: 1, ..Coverage::default() };
+        assert!(!coverage.is_complete());
+    }
+}
@@ -0,0 +1,343 @@
+use anchor_lang::prelude::*;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::tx::inner_tx::SwapInstruction;
+use crate::tx::swap_decoder::{is_anchor_instruction, InstructionContext, SwapDecoder, Transfer};
+
+pub const JUPITER_DCA_PROGRAM_ID: &str = "DCA265Vj8a9CEuX1eb1LWRnDT7uK6q1xMipnNyatn23M";
+pub const JUPITER_LIMIT_ORDER_PROGRAM_ID: &str = "jupoNjAxXgZ4rjzxzPMP4oxduvQsQtZzyknqvzYNrNu";
+pub const JUPITER_LIMIT_ORDER_V2_PROGRAM_ID: &str = "j1o2qRpjcyUwEvwtcfhEQefh773ZgjxcVRry7LDqg5X";
+
+// sha256("event:FilledEvent")[..8]
+const FILLED_EVENT_DISCRIMINATOR: [u8; 8] = [0x1c, 0x41, 0xbf, 0xd2, 0x7b, 0x55, 0x32, 0xbc];
+// sha256("event:TradeEvent")[..8]
+const TRADE_EVENT_DISCRIMINATOR: [u8; 8] = [0xbd, 0xdb, 0x7f, 0xd3, 0x4e, 0xe6, 0x61, 0xee];
+
+const DCA_ESCROW_INSTRUCTIONS: [&str; 6] = ["open_dca", "open_dca_v2", "deposit", "withdraw", "close_dca", "end_and_close"];
+const LIMIT_ORDER_FILL_INSTRUCTIONS: [&str; 2] = ["fill_order", "flash_fill_order"];
+const LIMIT_ORDER_ESCROW_INSTRUCTIONS: [&str; 3] = ["initialize_order", "cancel_order", "cancel_dust_order"];
+
+// DCA 每次成交发出的事件，fee 以 fee_mint（输出代币）收取
+#[derive(AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FilledEvent {
+    pub user_key: Pubkey,//32
+    pub dca_key: Pubkey,//32
+    pub input_mint: Pubkey,//32
+    pub output_mint: Pubkey,//32
+    pub in_amount: u64,//8
+    pub out_amount: u64,//8
+    pub fee_mint: Pubkey,//32
+    pub fee: u64,//8
+}
+
+// 限价单成交事件，v1 与 v2 的布局相同（v2 中 in/out 名为 making/taking），不包含 maker 与 mint
+#[derive(AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TradeEvent {
+    pub order_key: Pubkey,//32
+    pub taker: Pubkey,//32
+    pub remaining_in_amount: u64,//8
+    pub remaining_out_amount: u64,//8
+    pub in_amount: u64,//8
+    pub out_amount: u64,//8
+}
+
+// DCA 由 keeper 发起成交，资金从 DCA 托管账户流出，用户账户可能只收到输出代币，因此以 FilledEvent 为准
+pub struct JupiterDca;
+
+impl SwapDecoder for JupiterDca {
+    fn program_id(&self) -> Pubkey {
+        Pubkey::from_str_const(JUPITER_DCA_PROGRAM_ID)
+    }
+
+    fn name(&self) -> &'static str {
+        "jupiter_dca"
+    }
+
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction> {
+        let event = ctx.cpi_events(self.program_id(), &FILLED_EVENT_DISCRIMINATOR)
+            .into_iter()
+            .filter_map(|payload| FilledEvent::deserialize(&mut payload.as_slice()).ok())
+            .find(|event| event.user_key == *ctx.user)?;
+
+        // 用户实际收到的是扣除手续费后的数量
+        let fee = if event.fee_mint == event.output_mint { event.fee } else { 0 };
+        Some(SwapInstruction {
+            amm: self.program_id(),
+            input_mint: event.input_mint,
+            input_amount: event.in_amount,
+            output_mint: event.output_mint,
+            output_amount: event.out_amount.saturating_sub(fee),
+        })
+    }
+
+    fn is_escrow(&self, ctx: &InstructionContext) -> bool {
+        is_anchor_instruction(ctx.data, &DCA_ESCROW_INSTRUCTIONS)
+    }
+}
+
+// 限价单由 taker 成交：输入代币从订单托管账户转给 taker，输出代币直接转入 maker 的账户；用户可能是任意一方
+pub struct JupiterLimitOrder {
+    program_id: Pubkey,
+    name: &'static str,
+}
+
+impl JupiterLimitOrder {
+    pub fn v1() -> Self {
+        JupiterLimitOrder {
+            program_id: Pubkey::from_str_const(JUPITER_LIMIT_ORDER_PROGRAM_ID),
+            name: "jupiter_limit_order",
+        }
+    }
+
+    pub fn v2() -> Self {
+        JupiterLimitOrder {
+            program_id: Pubkey::from_str_const(JUPITER_LIMIT_ORDER_V2_PROGRAM_ID),
+            name: "jupiter_limit_order_v2",
+        }
+    }
+
+    // 用户作为 taker 成交他人的订单：付出 maker 想要的代币（事件的 out_amount），得到订单托管的代币（in_amount）
+    fn decode_taker(&self, ctx: &InstructionContext, transfers: &[Transfer], event: Option<&TradeEvent>) -> Option<SwapInstruction> {
+        let Some(event) = event else {
+            return ctx.user_swap(self.program_id);
+        };
+        let paid = transfers.iter().find(|transfer| transfer.source_owner == Some(*ctx.user))?;
+        // flash fill 中托管代币在前一条 pre_flash_fill_order 指令中已转给 taker
+        let received_mint = transfers
+            .iter()
+            .find(|transfer| transfer.destination_owner == Some(*ctx.user) && transfer.mint != paid.mint)
+            .map(|transfer| transfer.mint)
+            .or_else(|| ctx.token_accounts.mint_owned_by(&event.order_key))?;
+
+        Some(SwapInstruction {
+            amm: self.program_id,
+            input_mint: paid.mint,
+            input_amount: event.out_amount,
+            output_mint: received_mint,
+            output_amount: event.in_amount,
+        })
+    }
+}
+
+impl SwapDecoder for JupiterLimitOrder {
+    fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction> {
+        if !is_anchor_instruction(ctx.data, &LIMIT_ORDER_FILL_INSTRUCTIONS) {
+            return None;
+        }
+        let transfers = ctx.transfers();
+        let event = ctx.cpi_events(self.program_id, &TRADE_EVENT_DISCRIMINATOR)
+            .into_iter()
+            .find_map(|payload| TradeEvent::deserialize(&mut payload.as_slice()).ok());
+
+        // maker 的资金都在托管账户中，只有 taker 会从自己的账户转出
+        let is_taker = match &event {
+            Some(event) => event.taker == *ctx.user,
+            None => transfers.iter().any(|transfer| transfer.source_owner == Some(*ctx.user)),
+        };
+        if is_taker {
+            return self.decode_taker(ctx, &transfers, event.as_ref());
+        }
+
+        // 转入用户账户的即为输出
+        let received = transfers.iter().find(|transfer| transfer.destination_owner == Some(*ctx.user))?;
+        let output_amount = transfers
+            .iter()
+            .filter(|transfer| transfer.destination_owner == Some(*ctx.user) && transfer.mint == received.mint)
+            .map(|transfer| transfer.amount)
+            .sum::<u64>();
+        // 托管账户转出的、与输出不同的代币即为输入
+        let escrow_transfer = transfers
+            .iter()
+            .find(|transfer| transfer.mint != received.mint && transfer.source_owner != Some(*ctx.user));
+
+        let (input_mint, input_amount) = match event {
+            // flash fill 中托管账户的转出可能发生在前一条 pre_flash_fill_order 指令中，托管账户由订单账户持有
+            Some(event) => (
+                escrow_transfer
+                    .map(|transfer| transfer.mint)
+                    .or_else(|| ctx.token_accounts.mint_owned_by(&event.order_key))?,
+                event.in_amount,
+            ),
+            None => escrow_transfer.map(|transfer| (transfer.mint, transfer.amount))?,
+        };
+
+        Some(SwapInstruction {
+            amm: self.program_id,
+            input_mint,
+            input_amount,
+            output_mint: received.mint,
+            output_amount,
+        })
+    }
+
+    fn is_escrow(&self, ctx: &InstructionContext) -> bool {
+        is_anchor_instruction(ctx.data, &LIMIT_ORDER_ESCROW_INSTRUCTIONS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::{event::EVENT_IX_TAG_LE, solana_program::hash::hash};
+    use solana_sdk::bs58;
+    use solana_transaction_status::UiInstruction;
+
+    use crate::tx::swap_decoder::{anchor_discriminator, TokenAccounts};
+
+    fn event_instruction(program_id: &str, discriminator: &[u8; 8], payload: &[u8]) -> UiInstruction {
+        let mut data = EVENT_IX_TAG_LE.to_vec();
+        data.extend_from_slice(discriminator);
+        data.extend_from_slice(payload);
+        serde_json::from_value(serde_json::json!({
+            "programId": program_id,
+            "accounts": [],
+            "data": bs58::encode(&data).into_string(),
+            "stackHeight": 2
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_event_discriminators() {
+        assert_eq!(FILLED_EVENT_DISCRIMINATOR, hash(b"event:FilledEvent").to_bytes()[..8]);
+        assert_eq!(TRADE_EVENT_DISCRIMINATOR, hash(b"event:TradeEvent").to_bytes()[..8]);
+    }
+
+    #[test]
+    fn test_decode_dca_fill() {
+        let (user, input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mut payload = vec![];
+        for key in [user, Pubkey::new_unique(), input_mint, output_mint] {
+            payload.extend_from_slice(key.as_ref());
+        }
+        payload.extend_from_slice(&10_000_000u64.to_le_bytes());
+        payload.extend_from_slice(&2_000_000u64.to_le_bytes());
+        payload.extend_from_slice(output_mint.as_ref());
+        payload.extend_from_slice(&2_000u64.to_le_bytes());
+
+        let children = vec![event_instruction(JUPITER_DCA_PROGRAM_ID, &FILLED_EVENT_DISCRIMINATOR, &payload)];
+        let token_accounts = TokenAccounts::default();
+        let data = anchor_discriminator("fulfill_flash_fill");
+        let ctx = InstructionContext { user: &user, data: &data, children: &children, token_accounts: &token_accounts };
+
+        assert_eq!(JupiterDca.decode(&ctx), Some(SwapInstruction {
+            amm: JupiterDca.program_id(),
+            input_mint,
+            input_amount: 10_000_000,
+            output_mint,
+            output_amount: 1_998_000,
+        }));
+
+        let close = anchor_discriminator("close_dca");
+        let ctx = InstructionContext { user: &user, data: &close, children: &[], token_accounts: &token_accounts };
+        assert!(JupiterDca.is_escrow(&ctx));
+    }
+
+    fn token_accounts(accounts: &[(Pubkey, Pubkey, Pubkey)]) -> TokenAccounts {
+        let instructions = accounts
+            .iter()
+            .map(|(account, mint, owner)| serde_json::from_value(serde_json::json!({
+                "program": "spl-token",
+                "programId": "TokenkegQfeZyiNwAJbNbGCPFdR3aeNhbhQjWR4CZn5",
+                "parsed": {
+                    "type": "initializeAccount3",
+                    "info": { "account": account.to_string(), "mint": mint.to_string(), "owner": owner.to_string() }
+                },
+                "stackHeight": 2
+            })).unwrap())
+            .collect::<Vec<UiInstruction>>();
+        let mut token_accounts = TokenAccounts::default();
+        token_accounts.add_initialized(&instructions);
+        token_accounts
+    }
+
+    fn token_transfer(source: &Pubkey, destination: &Pubkey, authority: &Pubkey, amount: u64) -> UiInstruction {
+        serde_json::from_value(serde_json::json!({
+            "program": "spl-token",
+            "programId": "TokenkegQfeZyiNwAJbNbGCPFdR3aeNhbhQjWR4CZn5",
+            "parsed": {
+                "type": "transfer",
+                "info": {
+                    "source": source.to_string(),
+                    "destination": destination.to_string(),
+                    "authority": authority.to_string(),
+                    "amount": amount.to_string()
+                }
+            },
+            "stackHeight": 2
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_decode_limit_order_fills() {
+        // maker 以 100 USDC 的限价买入 42_000 个代币，托管账户由订单账户持有
+        let (maker, taker, order) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (usdc, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (escrow, maker_token, taker_usdc, taker_token) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let token_accounts = token_accounts(&[
+            (escrow, usdc, order),
+            (maker_token, mint, maker),
+            (taker_usdc, usdc, taker),
+            (taker_token, mint, taker),
+        ]);
+
+        let mut payload = vec![];
+        payload.extend_from_slice(order.as_ref());
+        payload.extend_from_slice(taker.as_ref());
+        for amount in [0u64, 0, 100_000_000, 42_000] {
+            payload.extend_from_slice(&amount.to_le_bytes());
+        }
+        let event = event_instruction(JUPITER_LIMIT_ORDER_PROGRAM_ID, &TRADE_EVENT_DISCRIMINATOR, &payload);
+        let escrow_transfer = token_transfer(&escrow, &taker_usdc, &order, 100_000_000);
+        let maker_transfer = token_transfer(&taker_token, &maker_token, &taker, 42_000);
+
+        let decoder = JupiterLimitOrder::v1();
+        let maker_swap = Some(SwapInstruction {
+            amm: decoder.program_id(),
+            input_mint: usdc,
+            input_amount: 100_000_000,
+            output_mint: mint,
+            output_amount: 42_000,
+        });
+        let taker_swap = Some(SwapInstruction {
+            amm: decoder.program_id(),
+            input_mint: mint,
+            input_amount: 42_000,
+            output_mint: usdc,
+            output_amount: 100_000_000,
+        });
+
+        // fill_order：托管账户的转出与转给 maker 在同一条指令中
+        let fill = anchor_discriminator("fill_order");
+        let children = vec![escrow_transfer, maker_transfer.clone(), event.clone()];
+        let ctx = InstructionContext { user: &maker, data: &fill, children: &children, token_accounts: &token_accounts };
+        assert_eq!(decoder.decode(&ctx), maker_swap);
+        // 用户是 taker
+        let ctx = InstructionContext { user: &taker, data: &fill, children: &children, token_accounts: &token_accounts };
+        assert_eq!(decoder.decode(&ctx), taker_swap);
+
+        // flash_fill_order：托管账户的转出在前一条指令中，输入代币由订单账户持有的托管账户确定
+        let flash_fill = anchor_discriminator("flash_fill_order");
+        let children = vec![maker_transfer, event];
+        let ctx = InstructionContext { user: &maker, data: &flash_fill, children: &children, token_accounts: &token_accounts };
+        assert_eq!(decoder.decode(&ctx), maker_swap);
+        let ctx = InstructionContext { user: &taker, data: &flash_fill, children: &children, token_accounts: &token_accounts };
+        assert_eq!(decoder.decode(&ctx), taker_swap);
+
+        // 订单的创建与取消只是托管资金的存取
+        let cancel = anchor_discriminator("cancel_order");
+        let ctx = InstructionContext { user: &maker, data: &cancel, children: &[], token_accounts: &token_accounts };
+        assert_eq!(decoder.decode(&ctx), None);
+        assert!(decoder.is_escrow(&ctx));
+    }
+}
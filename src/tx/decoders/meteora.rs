@@ -0,0 +1,55 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::tx::inner_tx::SwapInstruction;
+use crate::tx::swap_decoder::{is_anchor_instruction, InstructionContext, SwapDecoder};
+
+pub const METEORA_DLMM_PROGRAM_ID: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+pub const METEORA_POOLS_PROGRAM_ID: &str = "Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB";
+
+const DLMM_SWAP_INSTRUCTIONS: [&str; 6] = [
+    "swap",
+    "swap_exact_out",
+    "swap_with_price_impact",
+    "swap2",
+    "swap_exact_out2",
+    "swap_with_price_impact2",
+];
+
+pub struct MeteoraDlmm;
+
+impl SwapDecoder for MeteoraDlmm {
+    fn program_id(&self) -> Pubkey {
+        Pubkey::from_str_const(METEORA_DLMM_PROGRAM_ID)
+    }
+
+    fn name(&self) -> &'static str {
+        "meteora_dlmm"
+    }
+
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction> {
+        if !is_anchor_instruction(ctx.data, &DLMM_SWAP_INSTRUCTIONS) {
+            return None;
+        }
+        ctx.user_swap(self.program_id())
+    }
+}
+
+// Meteora Dynamic AMM
+pub struct MeteoraPools;
+
+impl SwapDecoder for MeteoraPools {
+    fn program_id(&self) -> Pubkey {
+        Pubkey::from_str_const(METEORA_POOLS_PROGRAM_ID)
+    }
+
+    fn name(&self) -> &'static str {
+        "meteora_pools"
+    }
+
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction> {
+        if !is_anchor_instruction(ctx.data, &["swap"]) {
+            return None;
+        }
+        ctx.user_swap(self.program_id())
+    }
+}
@@ -0,0 +1,5 @@
+pub mod raydium;
+pub mod orca;
+pub mod meteora;
+pub mod pump_fun;
+pub mod jupiter;
\ No newline at end of file
@@ -0,0 +1,26 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::tx::inner_tx::SwapInstruction;
+use crate::tx::swap_decoder::{is_anchor_instruction, InstructionContext, SwapDecoder};
+
+pub const WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+
+pub struct Whirlpool;
+
+impl SwapDecoder for Whirlpool {
+    fn program_id(&self) -> Pubkey {
+        Pubkey::from_str_const(WHIRLPOOL_PROGRAM_ID)
+    }
+
+    fn name(&self) -> &'static str {
+        "orca_whirlpool"
+    }
+
+    // two_hop_swap 的中间代币会流入并流出用户账户，按净流量汇总后只剩首尾两端
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction> {
+        if !is_anchor_instruction(ctx.data, &["swap", "swap_v2", "two_hop_swap", "two_hop_swap_v2"]) {
+            return None;
+        }
+        ctx.user_swap(self.program_id())
+    }
+}
@@ -0,0 +1,186 @@
+use anchor_lang::prelude::*;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::handle::base_token::WSOL;
+use crate::tx::inner_tx::SwapInstruction;
+use crate::tx::swap_decoder::{is_anchor_instruction, InstructionContext, SwapDecoder};
+
+pub const PUMP_FUN_PROGRAM_ID: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
+
+// TradeEvent 的 discriminator，sha256("event:TradeEvent")[..8]
+const TRADE_EVENT_DISCRIMINATOR: [u8; 8] = [0xbd, 0xdb, 0x7f, 0xd3, 0x4e, 0xe6, 0x61, 0xee];
+
+// TradeEvent 的前几个字段，之后的字段随程序升级不断增加
+#[derive(AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TradeEvent {
+    pub mint: Pubkey,//32
+    pub sol_amount: u64,//8 不含手续费
+    pub token_amount: u64,//8
+    pub is_buy: bool,//1
+    pub user: Pubkey,//32
+}
+
+// 紧跟 TradeEvent 的储备与协议手续费字段，旧版本的事件没有
+#[derive(AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TradeEventFee {
+    pub timestamp: i64,//8
+    pub virtual_sol_reserves: u64,//8
+    pub virtual_token_reserves: u64,//8
+    pub real_sol_reserves: u64,//8
+    pub real_token_reserves: u64,//8
+    pub fee_recipient: Pubkey,//32
+    pub fee_basis_points: u64,//8
+    pub fee: u64,//8
+}
+
+// 再之后的创作者手续费字段，更早的版本没有
+#[derive(AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TradeEventCreatorFee {
+    pub creator: Pubkey,//32
+    pub creator_fee_basis_points: u64,//8
+    pub creator_fee: u64,//8
+}
+
+// 返回事件与用户额外支付的 SOL 手续费（协议手续费 + 创作者手续费），缺少的字段按 0 计
+fn decode_trade_event(payload: &[u8]) -> Option<(TradeEvent, u64)> {
+    let mut rest = payload;
+    let event = TradeEvent::deserialize(&mut rest).ok()?;
+    let Ok(fee) = TradeEventFee::deserialize(&mut rest) else {
+        return Some((event, 0));
+    };
+    let creator_fee = TradeEventCreatorFee::deserialize(&mut rest).map_or(0, |creator| creator.creator_fee);
+    Some((event, fee.fee.saturating_add(creator_fee)))
+}
+
+// bonding curve 卖出时 SOL 直接从 curve 账户划转，没有转账指令，因此使用程序通过 emit_cpi 发出的 TradeEvent
+pub struct PumpFun;
+
+impl SwapDecoder for PumpFun {
+    fn program_id(&self) -> Pubkey {
+        Pubkey::from_str_const(PUMP_FUN_PROGRAM_ID)
+    }
+
+    fn name(&self) -> &'static str {
+        "pump_fun"
+    }
+
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction> {
+        if !is_anchor_instruction(ctx.data, &["buy", "sell", "buy_exact_sol_in"]) {
+            return None;
+        }
+        let (event, fee) = ctx.cpi_events(self.program_id(), &TRADE_EVENT_DISCRIMINATOR)
+            .into_iter()
+            .filter_map(|payload| decode_trade_event(&payload))
+            .find(|(event, _)| event.user == *ctx.user)?;
+
+        // sol_amount 不含手续费：买入时用户实际多付，卖出时实际少收
+        let wsol = Pubkey::from_str_const(WSOL);
+        let (input_mint, input_amount, output_mint, output_amount) = if event.is_buy {
+            (wsol, event.sol_amount.saturating_add(fee), event.mint, event.token_amount)
+        } else {
+            (event.mint, event.token_amount, wsol, event.sol_amount.saturating_sub(fee))
+        };
+        Some(SwapInstruction {
+            amm: self.program_id(),
+            input_mint,
+            input_amount,
+            output_mint,
+            output_amount,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::hash::hash;
+
+    #[test]
+    fn test_trade_event_discriminator() {
+        assert_eq!(TRADE_EVENT_DISCRIMINATOR, hash(b"event:TradeEvent").to_bytes()[..8]);
+    }
+
+    fn trade_event(mint: &Pubkey, user: &Pubkey, is_buy: bool, trailing: &[u8]) -> Vec<solana_transaction_status::UiInstruction> {
+        use anchor_lang::event::EVENT_IX_TAG_LE;
+        use solana_sdk::bs58;
+
+        let mut data = EVENT_IX_TAG_LE.to_vec();
+        data.extend_from_slice(&TRADE_EVENT_DISCRIMINATOR);
+        data.extend_from_slice(mint.as_ref());
+        data.extend_from_slice(&1_500_000_000u64.to_le_bytes());
+        data.extend_from_slice(&35_000_000_000u64.to_le_bytes());
+        data.push(u8::from(is_buy));
+        data.extend_from_slice(user.as_ref());
+        data.extend_from_slice(trailing);
+
+        vec![serde_json::from_value(serde_json::json!({
+            "programId": PUMP_FUN_PROGRAM_ID,
+            "accounts": [],
+            "data": bs58::encode(&data).into_string(),
+            "stackHeight": 2
+        })).unwrap()]
+    }
+
+    #[test]
+    fn test_decode_trade_event_with_trailing_fields() {
+        use crate::tx::swap_decoder::{anchor_discriminator, TokenAccounts};
+
+        let (mint, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // 只有 timestamp 等部分后续字段、没有手续费字段的旧版本事件
+        let children = trade_event(&mint, &user, true, &[0u8; 64]);
+        let token_accounts = TokenAccounts::default();
+        let buy = anchor_discriminator("buy");
+        let ctx = InstructionContext { user: &user, data: &buy, children: &children, token_accounts: &token_accounts };
+
+        assert_eq!(PumpFun.decode(&ctx), Some(SwapInstruction {
+            amm: PumpFun.program_id(),
+            input_mint: Pubkey::from_str_const(WSOL),
+            input_amount: 1_500_000_000,
+            output_mint: mint,
+            output_amount: 35_000_000_000,
+        }));
+
+        // 其他用户的交易
+        let other = Pubkey::new_unique();
+        let ctx = InstructionContext { user: &other, data: &buy, children: &children, token_accounts: &token_accounts };
+        assert_eq!(PumpFun.decode(&ctx), None);
+    }
+
+    #[test]
+    fn test_decode_trade_event_fees() {
+        use crate::tx::swap_decoder::{anchor_discriminator, TokenAccounts};
+
+        let (mint, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut trailing = vec![0u8; 40];// timestamp 与储备
+        trailing.extend_from_slice(Pubkey::new_unique().as_ref());
+        trailing.extend_from_slice(&95u64.to_le_bytes());
+        trailing.extend_from_slice(&14_250_000u64.to_le_bytes());
+        trailing.extend_from_slice(Pubkey::new_unique().as_ref());
+        trailing.extend_from_slice(&5u64.to_le_bytes());
+        trailing.extend_from_slice(&750_000u64.to_le_bytes());
+        // 之后版本新增的字段
+        trailing.push(1);
+
+        let token_accounts = TokenAccounts::default();
+        let decode = |is_buy: bool, trailing: &[u8], instruction: &str| {
+            let children = trade_event(&mint, &user, is_buy, trailing);
+            let data = anchor_discriminator(instruction);
+            let ctx = InstructionContext { user: &user, data: &data, children: &children, token_accounts: &token_accounts };
+            PumpFun.decode(&ctx).unwrap()
+        };
+
+        // 买入：用户支付 sol_amount + 协议手续费 + 创作者手续费
+        let swap = decode(true, &trailing, "buy");
+        assert_eq!(swap.input_amount, 1_515_000_000);
+        assert_eq!(swap.output_amount, 35_000_000_000);
+
+        // 卖出：用户实际收到 sol_amount 扣除手续费
+        let swap = decode(false, &trailing, "sell");
+        assert_eq!(swap.input_amount, 35_000_000_000);
+        assert_eq!(swap.output_amount, 1_485_000_000);
+
+        // 只有协议手续费、没有创作者手续费的版本
+        let swap = decode(true, &trailing[..88], "buy");
+        assert_eq!(swap.input_amount, 1_514_250_000);
+    }
+}
@@ -0,0 +1,68 @@
+use solana_sdk::pubkey::Pubkey;
+
+use crate::tx::inner_tx::SwapInstruction;
+use crate::tx::swap_decoder::{is_anchor_instruction, InstructionContext, SwapDecoder};
+
+pub const RAYDIUM_AMM_V4_PROGRAM_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+pub const RAYDIUM_CPMM_PROGRAM_ID: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+pub const RAYDIUM_CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+
+// AMM v4 不是 Anchor 程序，第一个字节为指令序号：9 swap_base_in，11 swap_base_out，16/17 为对应的 v2 版本
+const AMM_V4_SWAP_INSTRUCTIONS: [u8; 4] = [9, 11, 16, 17];
+
+pub struct RaydiumAmmV4;
+
+impl SwapDecoder for RaydiumAmmV4 {
+    fn program_id(&self) -> Pubkey {
+        Pubkey::from_str_const(RAYDIUM_AMM_V4_PROGRAM_ID)
+    }
+
+    fn name(&self) -> &'static str {
+        "raydium_amm_v4"
+    }
+
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction> {
+        if !AMM_V4_SWAP_INSTRUCTIONS.contains(ctx.data.first()?) {
+            return None;
+        }
+        ctx.user_swap(self.program_id())
+    }
+}
+
+pub struct RaydiumCpmm;
+
+impl SwapDecoder for RaydiumCpmm {
+    fn program_id(&self) -> Pubkey {
+        Pubkey::from_str_const(RAYDIUM_CPMM_PROGRAM_ID)
+    }
+
+    fn name(&self) -> &'static str {
+        "raydium_cpmm"
+    }
+
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction> {
+        if !is_anchor_instruction(ctx.data, &["swap_base_input", "swap_base_output"]) {
+            return None;
+        }
+        ctx.user_swap(self.program_id())
+    }
+}
+
+pub struct RaydiumClmm;
+
+impl SwapDecoder for RaydiumClmm {
+    fn program_id(&self) -> Pubkey {
+        Pubkey::from_str_const(RAYDIUM_CLMM_PROGRAM_ID)
+    }
+
+    fn name(&self) -> &'static str {
+        "raydium_clmm"
+    }
+
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction> {
+        if !is_anchor_instruction(ctx.data, &["swap", "swap_v2", "swap_router_base_in"]) {
+            return None;
+        }
+        ctx.user_swap(self.program_id())
+    }
+}
@@ -1,17 +1,19 @@
-use anchor_lang::{prelude::*, Discriminator};
-use solana_transaction_status::option_serializer::OptionSerializer;
-// use borsh::{BorshDeserialize, BorshSerialize};
-use solana_sdk::{bs58, pubkey::Pubkey};
-// use anchor_lang::prelude::borsh::BorshDeserialize;
-// use anchor_lang::prelude::borsh::BorshSerialize;
-// use anchor_lang::prelude::borsh::BorshSchema;
+use anchor_lang::{event::EVENT_IX_TAG_LE, prelude::*, Discriminator};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedTransaction, EncodedTransactionWithStatusMeta, UiInstruction,
+    UiMessage, UiParsedInstruction,
+};
+use solana_sdk::pubkey::Pubkey;
 
-// #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, BorshSchema)]
+use crate::tx::balance_delta;
+use crate::tx::swap_decoder::{self, InstructionContext, SwapDecoder, TokenAccounts};
 
-const DISCRIMINATOR_SIZE: usize = 8;
+pub const JUPITER_V6_PROGRAM_ID: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
 
+const DISCRIMINATOR_SIZE: usize = 8;
 
-#[event]
+// Jupiter V6 的 SwapEvent，discriminator 为 sha256("event:SwapEvent")[..8]
+#[event(discriminator = [0x40, 0xc6, 0xcd, 0xe8, 0x26, 0x08, 0x71, 0xe2])]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SwapInstruction {
     pub amm: Pubkey,//32
@@ -21,86 +23,356 @@ pub struct SwapInstruction {
     pub output_amount: u64,//8
 }
 
+// Jupiter V6 的 FeeEvent，discriminator 为 sha256("event:FeeEvent")[..8]
+// 平台/推荐人费用，从 swap 的输入或输出代币中扣除，account 为收费账户
+#[event(discriminator = [0x49, 0x4f, 0x4e, 0x7f, 0xb8, 0xd5, 0x0d, 0xdc])]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeEvent {
+    pub account: Pubkey,//32
+    pub mint: Pubkey,//32
+    pub amount: u64,//8
+}
+
+// 单条 emit_cpi 事件指令的解码结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodedEvent {
+    Swap(SwapInstruction),
+    Fee(FeeEvent),
+    Unknown([u8; DISCRIMINATOR_SIZE]),// 合法的事件指令，但不是已知的事件类型
+    NotEvent,// 不是 emit_cpi 事件指令（没有 event ix tag）
+    Malformed,// discriminator 匹配但数据无法解码
+}
+
+// 解析结果，unknown_events 为无法识别的 Jupiter V6 事件数量，parse_failures 为识别出类型但解码失败的数量
+#[derive(Debug, Clone, Default)]
+pub struct ParsedInnerTx {
+    pub swap_instructions: Vec<SwapInstruction>,
+    pub fee_events: Vec<FeeEvent>,
+    pub unknown_events: usize,
+    pub parse_failures: usize,
+    pub escrow_instructions: usize,// DCA/限价单的存入与取回，不是交易
+}
+
+// emit_cpi 的数据布局：EVENT_IX_TAG_LE(8) + 事件 discriminator(8) + borsh 编码的事件
+pub fn decode_event(data: &[u8]) -> DecodedEvent {
+    let Some((tag, rest)) = data.split_at_checked(DISCRIMINATOR_SIZE) else {
+        return DecodedEvent::NotEvent;
+    };
+    if tag != EVENT_IX_TAG_LE {
+        return DecodedEvent::NotEvent;
+    }
+    let Some((discriminator, payload)) = rest.split_at_checked(DISCRIMINATOR_SIZE) else {
+        return DecodedEvent::Malformed;
+    };
+
+    if discriminator == SwapInstruction::DISCRIMINATOR {
+        return match SwapInstruction::try_from_slice(payload) {
+            Ok(swap_instruction) => DecodedEvent::Swap(swap_instruction),
+            Err(_) => DecodedEvent::Malformed,
+        };
+    }
+    if discriminator == FeeEvent::DISCRIMINATOR {
+        return match FeeEvent::try_from_slice(payload) {
+            Ok(fee_event) => DecodedEvent::Fee(fee_event),
+            Err(_) => DecodedEvent::Malformed,
+        };
+    }
+
+    let mut unknown = [0u8; DISCRIMINATOR_SIZE];
+    unknown.copy_from_slice(discriminator);
+    DecodedEvent::Unknown(unknown)
+}
+
+// 遍历交易的外部指令及其内部指令：
+// - Jupiter V6 的 SwapEvent/FeeEvent 直接解码，Jupiter 路由内部调用的 AMM 已被 SwapEvent 覆盖，不再重复解码
+// - 其他程序按 program id 分发给对应的 SwapDecoder，解码后跳过其 CPI 产生的子指令
 pub fn parse(
-    meta: &solana_transaction_status::UiTransactionStatusMeta,
-) -> Vec<SwapInstruction> {
-    let mut swap_instructions: Vec<SwapInstruction> = vec![];
-    match meta.inner_instructions.as_ref() {
-        OptionSerializer::Some(inner_instructions) => {
-            for instruction in inner_instructions {
-                for inner_instruction in &instruction.instructions {
-                    match inner_instruction {
-                        solana_transaction_status::UiInstruction::Parsed(instruction_parsed) => {
-                            match instruction_parsed {
-                                solana_transaction_status::UiParsedInstruction::Parsed(parsed_instruction) => {
-                                    // 处理解析后的指令
-                                    if parsed_instruction.program == "spl-associated-token-account" || parsed_instruction.program == "spl-token" || parsed_instruction.program_id == "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4" {
-                                        
-                                    }
-                                },
-                                // 用于描述那些无法被 RPC 节点完全解析的指令，但提供了指令的基本信息和原始字节数据
-                                solana_transaction_status::UiParsedInstruction::PartiallyDecoded(parsed_instruction) => {
-                                    // 处理解析后的指令
-                                    if parsed_instruction.program_id == "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4" {
-                                        //let data = hex::decode("e445a52e51cb9a1d40c6cde8260871e2a5d5ca9e04cf5db590b714ba2fe32cb159133fc1c192b72257fd07d39cb0401e069b8857feab8184fb687f634618c035dac439dc1aeb3b5598a0f00000000001602c6103000000001d8ccf87ac0147bae756eb963a2ef6244c9691569a8ec08f0020a2eb8fbdb5a121c88d1000000000");
-                                        let data = bs58::decode(&parsed_instruction.data)
-                                            .into_vec();
-                                        if data.is_ok() {
-                                            let data = data.unwrap();
-                                            let (_, buffer) = data.split_at(DISCRIMINATOR_SIZE);
-                                            // println!("buffer: {:?} {:?}", buffer.len(), SwapInstruction::try_from_slice(buffer.split_at(8).1));
-                                            // println!("Discriminator: {:?}", discriminator);
-                                            // println!("Discriminator as hex: {:?}", SwapInstruction::DISCRIMINATOR);
-                                            // let res = SwapInstruction::DISCRIMINATOR.eq(discriminator).then(
-                                            //     || SwapInstruction::try_from_slice(buffer)
-                                            // );
-                                            let res = SwapInstruction::try_from_slice(buffer.split_at(8).1);
-                                            match res {
-                                                Ok(swap_instruction) => {
-
-                                                    #[cfg(test)]
-                                                    println!("Parsed Jupiter V6 Swap Instruction: {:?}", swap_instruction);
-
-                                                    swap_instructions.push(swap_instruction);
-                                                },
-                                                _ => {
-                                                    println!("Failed to parse Jupiter V6 Swap Instruction: {:?}", data);
-                                                }
-                                            }
-                                            // let swap_ix = anchor_lang::prelude::borsh::try_from_slice_with_schema::<SwapInstruction>(&data.unwrap());
-                                            // //let swap_ix = solana_sdk::borsh0_10::try_from_slice_unchecked::<SwapInstruction>(&data.unwrap());
-                                            // println!("Parsed Jupiter V6 Swap Instruction: {:?}", swap_ix);
-                                            // match swap_ix {
-                                            //     Ok(swap_instruction) => {
-                                            //         swap_instructions.push(
-                                            //             swap_instruction
-                                            //         )
-                                            //     },
-                                            //     Err(_) => {
-                                                    
-                                            //     }
-                                            // }
-                                        }
-                                    }
-                                }
-                            }
-                        },
-                        _ => {
-                            
-                        }
-                    }
-                }
-            }
-        },
-        OptionSerializer::None => {
+    user: &Pubkey,
+    transaction: &EncodedTransactionWithStatusMeta,
+    decoders: &[Box<dyn SwapDecoder>],
+) -> ParsedInnerTx {
+    let mut parsed = ParsedInnerTx::default();
+
+    let Some(meta) = transaction.meta.as_ref() else {
+        return parsed;
+    };
+    let inner_instructions = match meta.inner_instructions.as_ref() {
+        OptionSerializer::Some(inner_instructions) => inner_instructions,
+        _ => {
             #[cfg(test)]
             println!("No inner instructions found.");
+
+            return parsed;
+        }
+    };
+
+    let outer_instructions = match &transaction.transaction {
+        EncodedTransaction::Json(ui_transaction) => match &ui_transaction.message {
+            UiMessage::Parsed(message) => message.instructions.as_slice(),
+            UiMessage::Raw(_) => &[],
         },
-        OptionSerializer::Skip => {
-            #[cfg(test)]
-            println!("Skipping inner instructions.");
+        _ => &[],
+    };
+    let mut token_accounts = TokenAccounts::new(&balance_delta::account_keys(&transaction.transaction), meta);
+    token_accounts.add_initialized(
+        outer_instructions.iter().chain(inner_instructions.iter().flat_map(|set| set.instructions.iter())),
+    );
+    let jupiter = Pubkey::from_str_const(JUPITER_V6_PROGRAM_ID);
+    // SwapEvent 不包含用户信息，只有用户签名的交易中的 Jupiter 事件才属于该用户（排除 DCA/限价单 keeper 的路由）
+    let user_signed = balance_delta::signers(&transaction.transaction).is_none_or(|signers| signers.contains(user));
+    let find_decoder = |program: Option<Pubkey>| decoders.iter().find(|decoder| Some(decoder.program_id()) == program);
+
+    for instruction in inner_instructions {
+        let outer = outer_instructions.get(instruction.index as usize);
+        let outer_program = outer.and_then(swap_decoder::program_id);
+
+        // 用户直接调用 AMM
+        if let Some(decoder) = find_decoder(outer_program)
+            && let Some(data) = outer.and_then(swap_decoder::raw_data)
+        {
+            let ctx = InstructionContext { user, data: &data, children: &instruction.instructions, token_accounts: &token_accounts };
+            decode_with(decoder.as_ref(), &ctx, &mut parsed);
+            continue;
         }
+
+        let in_jupiter = outer_program == Some(jupiter);
+        // 已被覆盖的子树的 stack height，其下的子指令不再交给 AMM 解码器
+        let mut covered_height: Option<u32> = None;
+        for (i, inner_instruction) in instruction.instructions.iter().enumerate() {
+            let height = swap_decoder::stack_height(inner_instruction);
+            let covered = matches!((covered_height, height), (Some(covered), Some(height)) if height > covered);
+            if !covered {
+                covered_height = None;
+            }
+            let program = swap_decoder::program_id(inner_instruction);
+
+            if program == Some(jupiter) {
+                // 事件指令无法被 RPC 节点完全解析，以 PartiallyDecoded 的形式提供原始字节数据
+                let Some(data) = swap_decoder::raw_data(inner_instruction) else {
+                    parsed.parse_failures += 1;
+                    continue;
+                };
+                match decode_event(&data) {
+                    DecodedEvent::Swap(_) | DecodedEvent::Fee(_) if !user_signed => {},
+                    DecodedEvent::Swap(swap_instruction) => {
+                        #[cfg(test)]
+                        println!("Parsed Jupiter V6 Swap Instruction: {:?}", swap_instruction);
+
+                        parsed.swap_instructions.push(swap_instruction);
+                    },
+                    DecodedEvent::Fee(fee_event) => {
+                        #[cfg(test)]
+                        println!("Parsed Jupiter V6 Fee Event: {:?}", fee_event);
+
+                        parsed.fee_events.push(fee_event);
+                    },
+                    DecodedEvent::Unknown(_discriminator) => {
+                        #[cfg(test)]
+                        println!("Unknown Jupiter V6 event: {:?}", _discriminator);
+
+                        parsed.unknown_events += 1;
+                    },
+                    // 其他程序通过 CPI 调用的 Jupiter 路由，其内部的 AMM 调用由 SwapEvent 覆盖
+                    DecodedEvent::NotEvent => {
+                        if !covered {
+                            covered_height = height;
+                        }
+                    },
+                    DecodedEvent::Malformed => {
+                        parsed.parse_failures += 1;
+                        println!("Failed to parse Jupiter V6 event: {:?}", data);
+                    },
+                }
+                continue;
+            }
+
+            if in_jupiter || covered {
+                continue;
+            }
+            // 其他聚合器或程序通过 CPI 调用的 AMM
+            if let Some(decoder) = find_decoder(program)
+                && let Some(data) = swap_decoder::raw_data(inner_instruction)
+            {
+                let children = descendants(&instruction.instructions, i);
+                let ctx = InstructionContext { user, data: &data, children, token_accounts: &token_accounts };
+                decode_with(decoder.as_ref(), &ctx, &mut parsed);
+                covered_height = height;
+            }
+        }
+    }
+
+    parsed
+}
+
+fn decode_with(decoder: &dyn SwapDecoder, ctx: &InstructionContext, parsed: &mut ParsedInnerTx) {
+    if let Some(swap_instruction) = decoder.decode(ctx) {
+        #[cfg(test)]
+        println!("Parsed {} swap: {:?}", decoder.name(), swap_instruction);
+
+        parsed.swap_instructions.push(swap_instruction);
+    } else if decoder.is_escrow(ctx) {
+        parsed.escrow_instructions += 1;
+    }
+}
+
+// 第 index 条内部指令通过 CPI 产生的子指令：其后 stack height 更大的连续指令；
+// 旧交易没有 stack height 时，取其后连续的 token/system 转账
+fn descendants(instructions: &[UiInstruction], index: usize) -> &[UiInstruction] {
+    let rest = &instructions[index + 1..];
+    let end = match swap_decoder::stack_height(&instructions[index]) {
+        Some(height) => rest
+            .iter()
+            .position(|ix| swap_decoder::stack_height(ix).is_none_or(|h| h <= height))
+            .unwrap_or(rest.len()),
+        None => rest
+            .iter()
+            .position(|ix| !matches!(ix, UiInstruction::Parsed(UiParsedInstruction::Parsed(_))))
+            .unwrap_or(rest.len()),
+    };
+    &rest[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::hash::hash;
+    use solana_sdk::bs58;
+
+    // 真实交易中的 SwapEvent 指令数据
+    const SWAP_EVENT_HEX: &str = "e445a52e51cb9a1d40c6cde8260871e2a5d5ca9e04cf5db590b714ba2fe32cb159133fc1c192b72257fd07d39cb0401e069b8857feab8184fb687f634618c035dac439dc1aeb3b5598a0f00000000001602c6103000000001d8ccf87ac0147bae756eb963a2ef6244c9691569a8ec08f0020a2eb8fbdb5a121c88d1000000000";
+
+    fn hex(data: &str) -> Vec<u8> {
+        (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&data[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_swap_event_discriminator() {
+        assert_eq!(SwapInstruction::DISCRIMINATOR, &hash(b"event:SwapEvent").to_bytes()[..8]);
+    }
+
+    #[test]
+    fn test_fee_event_discriminator() {
+        assert_eq!(FeeEvent::DISCRIMINATOR, &hash(b"event:FeeEvent").to_bytes()[..8]);
+    }
+
+    #[test]
+    fn test_decode_fee_event() {
+        let fee = FeeEvent {
+            account: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            amount: 1_234_567,
+        };
+        let mut data = EVENT_IX_TAG_LE.to_vec();
+        data.extend_from_slice(FeeEvent::DISCRIMINATOR);
+        data.extend_from_slice(&borsh::to_vec(&fee).unwrap());
+        assert_eq!(decode_event(&data), DecodedEvent::Fee(fee));
+    }
+
+    #[test]
+    fn test_decode_swap_event() {
+        let DecodedEvent::Swap(swap) = decode_event(&hex(SWAP_EVENT_HEX)) else {
+            panic!("expected swap event");
+        };
+        assert_eq!(swap.input_amount, 56_700_000);
+        assert_eq!(swap.output_amount, 277_727_265);
+    }
+
+    // 用户用 USDC 在 outer_program 上买入 MINT，AMM 由 amm_program 以 CPI 调用（两者相同表示直接调用 AMM）
+    fn amm_transaction(outer_program: &str, amm_program: &str) -> EncodedTransactionWithStatusMeta {
+        const USER: &str = "J14Cg556roeBSgWFEKNTiSQeydMPRW6FZNB2zDMmSadQ";
+        const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+        const MINT: &str = "KMNo3nJsBXfcpJTVhZcXLW7RmTwTt4GVFE7suUBo9sS";
+        let pool = Pubkey::new_unique().to_string();
+        let accounts = (0..4).map(|_| Pubkey::new_unique().to_string()).collect::<Vec<_>>();
+        let swap_data = bs58::encode([9u8; 17]).into_string();
+        let key = |pubkey: &str| serde_json::json!({ "pubkey": pubkey, "writable": true, "signer": pubkey == USER, "source": "transaction" });
+        let balance = |index: usize, mint: &str, owner: &str| serde_json::json!({
+            "accountIndex": index,
+            "mint": mint,
+            "owner": owner,
+            "uiTokenAmount": { "uiAmount": null, "decimals": 6, "amount": "0", "uiAmountString": "" }
+        });
+        let transfer = |source: &str, destination: &str, amount: u64, height: u32| serde_json::json!({
+            "program": "spl-token",
+            "programId": "TokenkegQfeZyiNwAJbNbGCPFdR3aeNhbhQjWR4CZn5",
+            "parsed": { "type": "transfer", "info": { "source": source, "destination": destination, "authority": USER, "amount": amount.to_string() } },
+            "stackHeight": height
+        });
+        let direct = outer_program == amm_program;
+        let height = if direct { 2 } else { 3 };
+        let mut inner = vec![];
+        if !direct {
+            inner.push(serde_json::json!({ "programId": amm_program, "accounts": [], "data": swap_data, "stackHeight": 2 }));
+        }
+        inner.push(transfer(&accounts[0], &accounts[2], 100_000_000, height));
+        inner.push(transfer(&accounts[3], &accounts[1], 42_000, height));
+
+        serde_json::from_value(serde_json::json!({
+            "transaction": {
+                "signatures": [],
+                "message": {
+                    "accountKeys": [key(USER), key(&accounts[0]), key(&accounts[1]), key(&accounts[2]), key(&accounts[3])],
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": [{ "programId": outer_program, "accounts": [], "data": swap_data, "stackHeight": null }]
+                }
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [0, 0, 0, 0, 0],
+                "postBalances": [0, 0, 0, 0, 0],
+                "innerInstructions": [{ "index": 0, "instructions": inner }],
+                "preTokenBalances": [
+                    balance(1, USDC, USER), balance(2, MINT, USER), balance(3, USDC, &pool), balance(4, MINT, &pool)
+                ],
+                "postTokenBalances": []
+            }
+        }))
+        .expect("Failed to build transaction")
     }
 
-    swap_instructions
-}
\ No newline at end of file
+    #[test]
+    fn test_parse_amm_swaps() {
+        use crate::tx::decoders::raydium::RAYDIUM_AMM_V4_PROGRAM_ID;
+
+        let user = Pubkey::from_str_const("J14Cg556roeBSgWFEKNTiSQeydMPRW6FZNB2zDMmSadQ");
+        let decoders = swap_decoder::default_decoders();
+
+        // 直接调用 Raydium
+        let parsed = parse(&user, &amm_transaction(RAYDIUM_AMM_V4_PROGRAM_ID, RAYDIUM_AMM_V4_PROGRAM_ID), &decoders);
+        assert_eq!(parsed.swap_instructions.len(), 1);
+        assert_eq!(parsed.swap_instructions[0].input_amount, 100_000_000);
+        assert_eq!(parsed.swap_instructions[0].output_amount, 42_000);
+
+        // 其他程序通过 CPI 调用 Raydium
+        let router = Pubkey::new_unique().to_string();
+        let parsed = parse(&user, &amm_transaction(&router, RAYDIUM_AMM_V4_PROGRAM_ID), &decoders);
+        assert_eq!(parsed.swap_instructions.len(), 1);
+
+        // Jupiter 路由内部的 Raydium 调用由 SwapEvent 覆盖，不重复解码
+        let parsed = parse(&user, &amm_transaction(JUPITER_V6_PROGRAM_ID, RAYDIUM_AMM_V4_PROGRAM_ID), &decoders);
+        assert!(parsed.swap_instructions.is_empty());
+    }
+
+    #[test]
+    fn test_reject_non_matching_payloads() {
+        let mut data = hex(SWAP_EVENT_HEX);
+        // 其他事件类型，即使数据长度恰好能被解码也不能当作 swap
+        data[8] ^= 0xff;
+        assert!(matches!(decode_event(&data), DecodedEvent::Unknown(_)));
+
+        // 没有 emit_cpi tag 的普通指令
+        let mut data = hex(SWAP_EVENT_HEX);
+        data[0] ^= 0xff;
+        assert_eq!(decode_event(&data), DecodedEvent::NotEvent);
+
+        // discriminator 正确但数据被截断
+        let data = hex(SWAP_EVENT_HEX);
+        assert_eq!(decode_event(&data[..40]), DecodedEvent::Malformed);
+        assert_eq!(decode_event(&data[..4]), DecodedEvent::NotEvent);
+    }
+}
@@ -1,235 +1,682 @@
 use std::cmp;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use dashmap::DashMap;
-use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::RpcClientConfig;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
 use solana_client::rpc_config::RpcTransactionConfig;
 use solana_client::rpc_request::TokenAccountsFilter;
 use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
 use solana_sdk::signature::Signature;
-use solana_transaction_status::UiTransactionEncoding;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+use tokio::sync::Semaphore;
 use tokio::task::JoinSet;
-use crate::handle::handle_swap_item::Pnl;
-
-use crate::tx::{inner_tx, post_balance};
+use crate::config::app_config::{Config, FetchMode};
+use crate::error::app_error::{AppError, IndexerError};
+use crate::handle::handle_swap_item::{CostBasisPolicy, Pnl};
+use crate::handle::handle_tx::{SwapItem, SwapSumInfos};
+use crate::price::implied::ImpliedPrices;
+use crate::price::oracle::{PriceOracle, PriceOracleChain};
+
+use crate::tx::batch_rpc::{BatchError, BatchRpcClient};
+use crate::tx::coverage::Coverage;
+use crate::tx::mint_metadata::MintMetadataCache;
+use crate::tx::rpc_middleware::RpcMiddleware;
+use crate::tx::rpc_pool::EndpointMetrics;
+use crate::tx::swap_decoder::{self, SwapDecoder};
+use crate::tx::{balance_delta, inner_tx, network_fee, post_balance, token_2022};
 
 const JUPITER_V6_ID: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
-const RPC_URL: &str = "https://fluent-compatible-choice.solana-mainnet.quiknode.pro/a0bf531d3ea905572f5d6d4f008f76e2a7ea1ced/";//"https://solana-mainnet.gateway.tatum.io";//"https://go.getblock.us/a2e90ccfc62448eeb000ac41771bf260";//"https://chain.gawallet.org/solana-mainnet/";
+
+// getSignaturesForAddress 单页最多返回 1000 条
+const SIGNATURE_PAGE_SIZE: usize = 1000;
 
 #[derive(Clone)]
 struct CloneableRpcClient {
     inner: Arc<RpcClient>,
 }
 
+// 调用方给定的签名回溯边界，None 表示不限制
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SignatureBound {
+    pub max_signatures: Option<usize>,
+    pub since: Option<i64>,// 只统计 block_time 不早于该时间戳的交易
+}
+
+// 实际用于计算 PnL 的签名区间
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureWindow {
+    pub signature_count: usize,
+    pub newest_signature: Option<String>,
+    pub newest_block_time: Option<i64>,
+    pub oldest_signature: Option<String>,
+    pub oldest_block_time: Option<i64>,
+    pub complete: bool,// 是否已覆盖完整历史（或到达 since 边界），false 表示被 max_signatures 截断
+}
+
+#[derive(Debug, Clone)]
+pub struct SignatureHistory {
+    pub signatures: Vec<Signature>,
+    pub window: SignatureWindow,
+    pub block_times: HashMap<Signature, i64>,
+}
+
+impl SignatureHistory {
+    // 合并另一个地址的签名历史：按签名去重，区间取两者的并集
+    pub fn merge(&mut self, other: SignatureHistory) {
+        let mut seen = self.signatures.iter().copied().collect::<HashSet<_>>();
+        self.signatures.extend(other.signatures.into_iter().filter(|sig| seen.insert(*sig)));
+        self.block_times.extend(other.block_times);
+
+        let window = &mut self.window;
+        if other.window.newest_block_time > window.newest_block_time {
+            window.newest_signature = other.window.newest_signature;
+            window.newest_block_time = other.window.newest_block_time;
+        }
+        if other.window.oldest_block_time.is_some()
+            && (window.oldest_block_time.is_none() || other.window.oldest_block_time < window.oldest_block_time)
+        {
+            window.oldest_signature = other.window.oldest_signature;
+            window.oldest_block_time = other.window.oldest_block_time;
+        }
+        window.complete &= other.window.complete;
+        window.signature_count = self.signatures.len();
+    }
+
+    // 合并多个地址后按时间从新到旧排序，只保留所有被截断的地址都已覆盖到的区间（不早于 covered_since），
+    // 再按 max_signatures 截断，使结果是一段连续的时间区间
+    pub fn truncate(&mut self, covered_since: Option<i64>, max_signatures: Option<usize>) {
+        // 没有 block_time 的签名多为刚确认的交易，视为最新
+        let block_times = &self.block_times;
+        self.signatures.sort_by_key(|sig| cmp::Reverse(block_times.get(sig).copied().unwrap_or(i64::MAX)));
+
+        let len = self.signatures.len();
+        if let Some(covered_since) = covered_since {
+            self.signatures.retain(|sig| block_times.get(sig).is_none_or(|block_time| *block_time >= covered_since));
+        }
+        if let Some(max_signatures) = max_signatures {
+            self.signatures.truncate(max_signatures);
+        }
+
+        let window = &mut self.window;
+        if self.signatures.len() < len {
+            window.complete = false;
+        }
+        window.newest_signature = self.signatures.first().map(Signature::to_string);
+        window.newest_block_time = self.signatures.first().and_then(|sig| block_times.get(sig).copied());
+        window.oldest_signature = self.signatures.last().map(Signature::to_string);
+        window.oldest_block_time = self.signatures.last().and_then(|sig| block_times.get(sig).copied());
+        window.signature_count = self.signatures.len();
+    }
+}
+
 pub struct JupiterV6Indexer {
     client: CloneableRpcClient,
+    middleware: RpcMiddleware,
+    batch_client: BatchRpcClient,
+    batch_supported: Arc<AtomicBool>,// 节点拒绝过 batch 请求后不再尝试
+    config: Arc<Config>,
+    commitment: CommitmentConfig,
+    decoders: Arc<Vec<Box<dyn SwapDecoder>>>,// 直接在 Raydium、Orca 等 AMM 上的交易
+    mint_metadata: MintMetadataCache,
+    price_oracle: Arc<dyn PriceOracle>,
     _jupiter_v6_id: Pubkey,
 }
 
 impl JupiterV6Indexer {
 
-    pub fn new() -> Self {
-        let client = Arc::new(RpcClient::new_with_commitment(RPC_URL.to_string(), CommitmentConfig::finalized()));
+    pub fn new(config: Arc<Config>) -> Self {
+        // Config::load 时已校验过 commitment
+        let commitment = config.rpc.commitment_config().unwrap_or(CommitmentConfig::finalized());
+        // RpcClient 与 batch 请求共用同一个中间件（同一个令牌桶）
+        let middleware = RpcMiddleware::new(&config.rpc);
+        let client = Arc::new(RpcClient::new_sender(middleware.clone(), RpcClientConfig::with_commitment(commitment)));
+        let batch_client = BatchRpcClient::new(middleware.clone());
         let _jupiter_v6_id = Pubkey::from_str(JUPITER_V6_ID).expect("Failed to parse Jupiter V6 ID");
 
         let client = CloneableRpcClient {
-            inner: client.clone(),
+            inner: client,
         };
+        let price_oracle = Arc::new(PriceOracleChain::from_config(&config.price));
 
         JupiterV6Indexer {
             client,
+            middleware,
+            batch_client,
+            batch_supported: Arc::new(AtomicBool::new(true)),
+            config,
+            commitment,
+            decoders: Arc::new(swap_decoder::default_decoders()),
+            mint_metadata: MintMetadataCache::new(),
+            price_oracle,
             _jupiter_v6_id,
         }
     }
 
+    pub fn rpc_metrics(&self) -> Vec<EndpointMetrics> {
+        self.middleware.metrics()
+    }
+
     pub async fn get_jupiter_v6_txs(
         &self,
         user_pubkey: &Pubkey,
         token_pubkey: &Pubkey,
-    ) -> Pnl {
-
-        let associated_token_account = self.client.inner.get_token_accounts_by_owner(user_pubkey, TokenAccountsFilter::Mint(token_pubkey.clone()));
-        if associated_token_account.is_err() {
-            eprintln!("Error fetching associated token account: {}", associated_token_account.err().unwrap());
-            return Pnl::default();
-        }
-        let associated_token_account = associated_token_account;
-        if associated_token_account.is_err() {
-            eprintln!("No associated token account found for user: {}", user_pubkey);
-            return Pnl::default();
-        }
-        let associated_token_account = associated_token_account.unwrap();
-        if associated_token_account.is_empty() {
-            eprintln!("No associated token account found for user: {}", user_pubkey);
-            return Pnl::default();
-        }
-        let associated_token_account = &associated_token_account[0].pubkey;
-        let associated_token_account = Pubkey::from_str(&associated_token_account);
-        if associated_token_account.is_err() {
-            return Pnl::default();
-        }
-        let associated_token_account = associated_token_account.unwrap();
+        bound: SignatureBound,
+        cost_basis: CostBasisPolicy,
+    ) -> Result<Pnl, AppError> {
+
+        let token_accounts = match self.client.inner.get_token_accounts_by_owner(user_pubkey, TokenAccountsFilter::Mint(*token_pubkey)).await {
+            Ok(accounts) => accounts
+                .iter()
+                .filter_map(|account| Pubkey::from_str(&account.pubkey).ok())
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                return Err(IndexerError::Rpc(format!("Error fetching token accounts: {}", e)).into());
+            }
+        };
 
+        // 仓位清空并关闭 ATA 后这里为空，此时仍可通过 owner 钱包本身的签名找回历史交易
         #[cfg(test)]
-        println!("Associated Token Account: {:?}", associated_token_account);// 默认获取第一个关联代币账户
+        println!("Token Accounts: {:?}", token_accounts);
+
+        // 按 mint 过滤时节点会根据 mint 所属的程序查询，Token-2022 的账户同样可以获取；转账费率需要读取 mint 账户的扩展
+        let transfer_fee_config = match self.client.inner.get_account(token_pubkey).await {
+            Ok(account) => token_2022::transfer_fee_config(&account.owner, &account.data),
+            Err(e) => {
+                eprintln!("Error fetching mint account {}: {}, ignoring transfer fees", token_pubkey, e);
+                None
+            }
+        };
 
-        let signatures = self.get_transaction_signatures(&associated_token_account).await;
+        let SignatureHistory { signatures, window, .. } = self.get_merged_signatures(user_pubkey, &token_accounts, bound).await?;
 
         #[cfg(test)]
-        if let Some(signatures) = signatures.clone() {
-            for signature in signatures {
-                println!("Transaction Signature: {}", signature);
-            }
-        } else {
-            println!("No transactions found for the user.");
-        }
-
-        //#[cfg(not(test))]
-        match signatures {
-            Some(signatures) => {
-                // 分组
-                let mut tasks = JoinSet::new();
-                let signatures = Arc::new(signatures);
-                //let signatures = Arc::new(vec![Signature::from_str("4NXAjsFhCwDczensh61NQdrcVRNMdtF6g8ytkJEdNQFirALr1Fg3yFoDXqU5AyP8PhzUXSHA8BDySVevLCtCkRPj").unwrap()]);
-                let txs = Arc::new(DashMap::new());
-
-                // 同时保持最多25个连接
-                for chunk in signatures.chunks(cmp::max(signatures.len() / 25, 1)) {
-                    let chunk = chunk.to_vec();
-                    let client = Arc::clone(&self.client.inner);
-                    let txs = Arc::clone(&txs);
-
-                    tasks.spawn(async move {
-                        for sig in chunk {
-                            let attemp_times = 5;
-                            // 重试获取交易
-                            for _ in 0..attemp_times {
-                                if let Some(tx) = client.get_transaction_with_config(
-                                    &sig,
-                                    RpcTransactionConfig {
-                                        encoding: Some(UiTransactionEncoding::JsonParsed),
-                                        commitment: Some(CommitmentConfig::finalized()),
-                                        max_supported_transaction_version: Some(1), // 关键：声明支持的事务版本；事务版本 0 是 Solana 当前默认的事务版本，表示使用基础的事务格式。
-                                    },
-                                ).ok() {
-                                    txs.insert(sig, tx);
+        for signature in &signatures {
+            println!("Transaction Signature: {}", signature);
+        }
 
-                                    #[cfg(test)]
-                                    println!("Successfully fetched transaction for signature: {}", sig);
+        if signatures.is_empty() {
+            return Err(IndexerError::NoTransactions(user_pubkey.to_string()).into());
+        }
 
-                                    break; // 成功获取交易后跳出重试循环
-                                } else {
+        let txs = self.fetch_transactions(&signatures).await;
+
+        let fetched = txs.iter().map(|(sig, _)| *sig).collect::<HashSet<_>>();
+        let failed_signatures = signatures
+            .iter()
+            .filter(|sig| !fetched.contains(sig))
+            .map(|sig| sig.to_string())
+            .collect::<Vec<_>>();
+        let mut coverage = Coverage {
+            signatures_discovered: signatures.len(),
+            transactions_fetched: txs.len(),
+            fetch_failed: failed_signatures.len(),
+            failed_signatures,
+            ..Coverage::default()
+        };
+        if coverage.fetch_failed > 0 {
+            eprintln!("Failed to fetch {} of {} transactions for user {}", coverage.fetch_failed, coverage.signatures_discovered, user_pubkey);
+        }
 
-                                    #[cfg(test)]
-                                    eprintln!("Failed to fetch transaction for signature: {}", sig);
+        let mut swap_sum_infos = vec![];
+        let mut transfer_items = vec![];
+
+        // 获取数据
+        txs.iter().for_each(|(sig, value)| {
+            let block_time = value.block_time.unwrap_or(0) as u64;
+            let sig = *sig;
+            match value.transaction.meta.as_ref() {
+                Some(meta) => {
+                    let parsed = inner_tx::parse(user_pubkey, &value.transaction, &self.decoders);
+                    // owner 钱包的签名包含大量与该 mint 无关的交易，只保留 token balances 中涉及该 mint 的交易，
+                    // 以及解码出涉及该 mint 的 swap 的交易（如限价单卖出时该 mint 从订单托管账户转出，不在用户的 token balances 中）
+                    let swaps_mint = parsed.swap_instructions
+                        .iter()
+                        .any(|swap| swap.input_mint == *token_pubkey || swap.output_mint == *token_pubkey);
+                    if !swaps_mint && !post_balance::references_mint(user_pubkey, token_pubkey, meta) {
+                        coverage.skipped_unrelated += 1;
+
+                        #[cfg(test)]
+                        println!("Skipping transaction not touching mint: {}", sig);
+                        return;
+                    }
 
-                                    continue;
-                                };
-                            }
-                        }
-                    });
+                    #[cfg(test)]
+                    println!("Processing transaction: {} at block time: {}", sig, block_time);
 
-                }
+                    coverage.parse_failures += parsed.parse_failures;
+                    coverage.unknown_events += parsed.unknown_events;
+                    let deltas = balance_delta::balance_deltas(user_pubkey, &value.transaction);
+                    let token_data = post_balance::parse_balance(user_pubkey, meta);
+                    let network_fee = network_fee::paid_fee(user_pubkey, &value.transaction);
 
-                // 等待所有任务完成
-                while let Some(res) = tasks.join_next().await {
-                    match res {
-                        Ok(_) => {},
-                        Err(e) => eprintln!("Error in task: {}", e),
+                    if parsed.swap_instructions.is_empty() && parsed.escrow_instructions > 0 {
+                        coverage.skipped_non_swap += 1;
+                        return;
                     }
-                }
-
-                let mut swap_sum_infos = vec![];
-
-                // 获取数据
-                txs.iter().for_each(|entry| {
-                    let value = entry.value();
-                    let block_time = value.block_time.unwrap_or(0) as u64;
-                    let sig = entry.key().clone();
-                    match value.transaction.meta.clone() {
-                        Some(meta) => {
-                            #[cfg(test)]
-                            println!("Processing transaction: {} at block time: {}", sig, block_time);
-
-                            let swap_data = inner_tx::parse(&meta);
-                            let token_data = post_balance::parse_balance(user_pubkey, &meta);
-                            swap_sum_infos.push(crate::handle::handle_tx::SwapSumInfos::new(swap_data, token_data, block_time, sig));
-                        },
-                        None => {
-                            println!("No meta data found for transaction: {}", entry.key());
+                    if parsed.swap_instructions.is_empty() {
+                        // 没有 swap 事件：该 mint 与其他代币反向变化时按余额变化视为一笔交易，否则是转账、开关账户等
+                        let trade = balance_delta::net_trade(&deltas);
+                        let net_amount = trade.net_amount(token_pubkey);
+                        if net_amount == 0 {
+                            coverage.skipped_non_swap += 1;
+                            return;
+                        }
+                        // 只有该 mint 单向变化：转入（空投、其他钱包、交易所提币）或转出
+                        if (net_amount > 0 && trade.inputs.is_empty()) || (net_amount < 0 && trade.outputs.is_empty()) {
+                            let Some(delta) = deltas.iter().find(|delta| delta.mint == *token_pubkey) else {
+                                return;
+                            };
+                            coverage.transfers += 1;
+                            let amount = delta.delta as f64 / 10f64.powi(i32::from(delta.decimals));
+                            transfer_items.push(SwapItem::transfer(*token_pubkey, amount, block_time, sig));
+                            return;
                         }
+                        coverage.delta_fallbacks += 1;
+                        swap_sum_infos.push(SwapSumInfos::new(vec![], parsed.fee_events, token_data, network_fee, block_time, sig).with_trade(trade));
+                        return;
+                    }
+
+                    let mut infos = SwapSumInfos::new(parsed.swap_instructions, parsed.fee_events, token_data, network_fee, block_time, sig);
+                    let mut fee_amount = infos.fee_data.iter().filter(|fee| fee.mint == *token_pubkey).map(|fee| fee.amount).sum::<u64>();
+                    // 买入时 Token-2022 的转账费用从到账数量中扣除：优先取余额变化中实际扣除的数量，
+                    // 无法推算时按交易所在 epoch 的费率计算；费率已被之后的更新覆盖时按 mint 中最早的费率近似
+                    if let Some(config) = &transfer_fee_config
+                        && let Ok(received) = u64::try_from(infos.trade.net_amount(token_pubkey))
+                    {
+                        let delta = deltas.iter().find(|delta| delta.mint == *token_pubkey).map_or(0, |delta| delta.delta);
+                        let withheld = token_2022::withheld_fee(received, fee_amount, delta);
+                        let expected = token_2022::epoch_transfer_fee(config, value.slot)
+                            .map(|transfer_fee| received - token_2022::received_amount(&transfer_fee, received));
+                        let transfer_fee = withheld.or(expected).unwrap_or_else(|| {
+                            coverage.transfer_fee_approximate += 1;
+                            received - token_2022::received_amount(&config.older_transfer_fee, received)
+                        });
+                        infos = infos.with_transfer_fee(transfer_fee);
+                        // 费率已知时仍按费率交叉校验，不让余额变化掩盖事件的错误
+                        fee_amount += expected.unwrap_or(transfer_fee);
                     }
-                });
+                    if !balance_delta::cross_check(&infos.trade, fee_amount, &deltas, token_pubkey) {
+                        coverage.delta_mismatches += 1;
+                        eprintln!("Swap events disagree with balance changes for {} in {}", token_pubkey, sig);
+                    }
+                    swap_sum_infos.push(infos);
+                },
+                None => {
+                    coverage.missing_meta += 1;
+                    eprintln!("No meta data found for transaction: {}", sig);
+                }
+            }
+        });
+
+        // 交易中没有精度的 mint（对手方账户已关闭、只出现在事件中）从链上 mint 账户补充，
+        // 目标 mint 总是读取，用于返回名称与符号
+        let mut mints = HashSet::from([*token_pubkey]);
+        for infos in &swap_sum_infos {
+            let legs = infos.trade.inputs.iter().chain(infos.trade.outputs.iter()).map(|leg| leg.mint);
+            mints.extend(
+                legs.chain(infos.fee_data.iter().map(|fee| fee.mint))
+                    .filter(|mint| !infos.token_data.iter().any(|balance| balance.mint == *mint)),
+            );
+        }
+        let metadata = self.mint_metadata.resolve(&self.client.inner, mints).await;
+        let mint_decimals = metadata
+            .iter()
+            .map(|(mint, metadata)| (*mint, metadata.decimals))
+            .collect::<HashMap<_, _>>();
+        let swap_sum_infos = swap_sum_infos
+            .into_iter()
+            .map(|infos| infos.with_mint_decimals(mint_decimals.clone()))
+            .collect::<Vec<_>>();
+
+        let mut sort_swap_items = self.build_swap_items(token_pubkey, swap_sum_infos.clone(), Arc::clone(&self.price_oracle)).await;
+
+        // 对手方与目标代币都没有价格来源的交易，按相邻交易中目标代币的隐含价格重新估值
+        let unpriced = sort_swap_items
+            .iter()
+            .filter(|item| item.buy_amount == 0.0 && item.sell_amount == 0.0)
+            .map(|item| item.sig)
+            .collect::<HashSet<_>>();
+        let implied = ImpliedPrices::from_items(token_pubkey, &sort_swap_items)
+            .with_max_distance(self.config.price.implied_price_max_distance_secs);
+        if !unpriced.is_empty() && !implied.is_empty() {
+            let fallback: Arc<dyn PriceOracle> = Arc::new(PriceOracleChain::new(vec![Arc::clone(&self.price_oracle), Arc::new(implied)]));
+            let unpriced_infos = swap_sum_infos
+                .into_iter()
+                .filter(|infos| unpriced.contains(&infos.sig))
+                .collect::<Vec<_>>();
+            let repriced = self.build_swap_items(token_pubkey, unpriced_infos, fallback).await;
+            coverage.implied_priced = repriced
+                .iter()
+                .filter(|item| item.buy_amount > 0.0 || item.sell_amount > 0.0)
+                .count();
+            sort_swap_items.retain(|item| !unpriced.contains(&item.sig));
+            sort_swap_items.extend(repriced);
+        }
+        // 重新估值后仍无法定价的交易不计入 PnL，需要在覆盖情况中体现
+        coverage.unpriced = sort_swap_items
+            .iter()
+            .filter(|item| item.amount != 0.0 && item.buy_amount == 0.0 && item.sell_amount == 0.0)
+            .count();
+        if coverage.unpriced > 0 {
+            eprintln!("{} swaps of {} for user {} could not be priced", coverage.unpriced, token_pubkey, user_pubkey);
+        }
+        sort_swap_items.extend(transfer_items);
+
+        // 按照 timestamp 排序
+        sort_swap_items.sort_by_key(|item| item.timestamp);
+
+        let mut pnl = Pnl::new(
+            *token_pubkey,
+            sort_swap_items,
+            cost_basis,
+            self.price_oracle.as_ref(),
+            Some(self.config.price.implied_price_max_distance_secs),
+        ).await?;
+        if let Some(metadata) = metadata.get(token_pubkey) {
+            pnl.token_name = metadata.name.clone();
+            pnl.token_symbol = metadata.symbol.clone();
+        }
+        pnl.complete = window.complete && coverage.is_complete();
+        pnl.window = Some(window);
+        pnl.coverage = Some(coverage);
+        Ok(pnl)
+    }
+
+    fn transaction_config(&self) -> RpcTransactionConfig {
+        RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::JsonParsed),
+            commitment: Some(self.commitment),
+            max_supported_transaction_version: Some(1), // 关键：声明支持的事务版本；事务版本 0 是 Solana 当前默认的事务版本，表示使用基础的事务格式。
+        }
+    }
 
-                let mut tasks = JoinSet::new();
-                let swap_items = Arc::new(DashMap::new());
+    async fn fetch_transactions(
+        &self,
+        signatures: &[Signature],
+    ) -> Vec<(Signature, EncodedConfirmedTransactionWithStatusMeta)> {
+        match self.config.rpc.fetch_mode {
+            FetchMode::Single => self.fetch_transactions_single(signatures).await,
+            FetchMode::Batch => self.fetch_transactions_batch(signatures).await,
+        }
+    }
 
-                for chunk in swap_sum_infos.chunks(cmp::max(swap_sum_infos.len() / 25, 1)) {
-                    let chunk = chunk.to_vec();
-                    let token_pubkey = token_pubkey.clone();
-                    let swap_items = Arc::clone(&swap_items);
+    // 每 batch_size 个签名合并为一个 JSON-RPC batch 请求；单条失败或整个 batch 被拒绝的签名回退到单笔请求
+    async fn fetch_transactions_batch(
+        &self,
+        signatures: &[Signature],
+    ) -> Vec<(Signature, EncodedConfirmedTransactionWithStatusMeta)> {
+        let permits = Arc::new(Semaphore::new(self.config.rpc.concurrency));
+        let mut tasks = JoinSet::new();
+
+        for chunk in signatures.chunks(self.config.rpc.batch_size) {
+            let chunk = chunk.to_vec();
+            let batch_client = self.batch_client.clone();
+            let batch_supported = Arc::clone(&self.batch_supported);
+            let permits = Arc::clone(&permits);
+            let tx_config = self.transaction_config();
+
+            tasks.spawn(async move {
+                let Ok(_permit) = permits.acquire_owned().await else {
+                    return (vec![], chunk);
+                };
+                if !batch_supported.load(Ordering::Relaxed) {
+                    return (vec![], chunk);
+                }
+                match batch_client.get_transactions(&chunk, tx_config).await {
+                    Ok(items) => {
+                        let mut fetched = vec![];
+                        let mut failed = vec![];
+                        for (sig, item) in items {
+                            match item {
+                                Ok(tx) => fetched.push((sig, tx)),
+                                Err(_e) => {
+                                    #[cfg(test)]
+                                    eprintln!("Failed to fetch transaction in batch: {} {}", sig, _e);
 
-                    tasks.spawn(async move {
-                        for swap_sum_info in chunk {
-                            let sig = swap_sum_info.sig.clone();
-                            let swap_item = crate::handle::handle_tx::SwapItem::new(token_pubkey, swap_sum_info).await;
-                            swap_items.insert(sig, swap_item);
+                                    failed.push(sig);
+                                }
+                            }
                         }
-                    });
+                        (fetched, failed)
+                    },
+                    Err(BatchError::Rejected(e)) => {
+                        eprintln!("RPC rejected batch requests, falling back to single calls: {}", e);
+                        batch_supported.store(false, Ordering::Relaxed);
+                        (vec![], chunk)
+                    },
+                    Err(BatchError::Transport(e)) => {
+                        eprintln!("Batch request failed, retrying with single calls: {}", e);
+                        (vec![], chunk)
+                    },
                 }
+            });
+        }
 
-                // 等待所有任务完成
-                while let Some(res) = tasks.join_next().await {
-                    match res {
-                        Ok(_) => {},
-                        Err(e) => eprintln!("Error in task: {}", e),
+        let mut txs = vec![];
+        let mut failed = vec![];
+        // 等待所有任务完成
+        while let Some(res) = tasks.join_next().await {
+            match res {
+                Ok((fetched, chunk_failed)) => {
+                    txs.extend(fetched);
+                    failed.extend(chunk_failed);
+                },
+                Err(e) => eprintln!("Error in task: {}", e),
+            }
+        }
+
+        if !failed.is_empty() {
+            txs.extend(self.fetch_transactions_single(&failed).await);
+        }
+        txs
+    }
+
+    // 并发获取交易详情，同一时刻最多 concurrency 个请求在途
+    async fn fetch_transactions_single(
+        &self,
+        signatures: &[Signature],
+    ) -> Vec<(Signature, EncodedConfirmedTransactionWithStatusMeta)> {
+        let permits = Arc::new(Semaphore::new(self.config.rpc.concurrency));
+        let mut tasks = JoinSet::new();
+
+        for sig in signatures.iter().copied() {
+            let client = Arc::clone(&self.client.inner);
+            let permits = Arc::clone(&permits);
+            let tx_config = self.transaction_config();
+
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await.ok()?;
+                // 限流与重试由 RpcMiddleware 负责，这里失败即为不可重试或重试耗尽
+                match client.get_transaction_with_config(&sig, tx_config).await {
+                    Ok(tx) => {
+                        #[cfg(test)]
+                        println!("Successfully fetched transaction for signature: {}", sig);
+
+                        Some((sig, tx))
+                    },
+                    Err(e) => {
+                        eprintln!("Failed to fetch transaction for signature: {} {}", sig, e);
+                        None
                     }
                 }
+            });
+        }
 
-                let mut sort_swap_items = swap_items.iter()
-                    .map(|entry| entry.value().clone())
-                    .collect::<Vec<_>>();
+        let mut txs = vec![];
+        // 等待所有任务完成
+        while let Some(res) = tasks.join_next().await {
+            match res {
+                Ok(Some(tx)) => txs.push(tx),
+                Ok(None) => {},
+                Err(e) => eprintln!("Error in task: {}", e),
+            }
+        }
+        txs
+    }
 
-                // 按照 timestamp 排序
-                sort_swap_items.sort_by(|a,b| a.timestamp.cmp(&b.timestamp));
+    // 并发对每笔交易进行估值（涉及历史价格请求），同样受 concurrency 限制
+    async fn build_swap_items(
+        &self,
+        token_pubkey: &Pubkey,
+        swap_sum_infos: Vec<SwapSumInfos>,
+        price_oracle: Arc<dyn PriceOracle>,
+    ) -> Vec<SwapItem> {
+        let permits = Arc::new(Semaphore::new(self.config.rpc.concurrency));
+        let mut tasks = JoinSet::new();
+
+        for swap_sum_info in swap_sum_infos {
+            let token_pubkey = *token_pubkey;
+            let permits = Arc::clone(&permits);
+            let price_oracle = Arc::clone(&price_oracle);
+
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await.ok()?;
+                Some(SwapItem::new(token_pubkey, swap_sum_info, price_oracle.as_ref()).await)
+            });
+        }
 
-                let pnl = Pnl::new(token_pubkey.clone(), sort_swap_items).await;
-                return pnl;
+        let mut swap_items = vec![];
+        // 等待所有任务完成
+        while let Some(res) = tasks.join_next().await {
+            match res {
+                Ok(Some(swap_item)) => swap_items.push(swap_item),
+                Ok(None) => {},
+                Err(e) => eprintln!("Error in task: {}", e),
+            }
+        }
+        swap_items
+    }
 
-            },
-            None => {
-                println!("No signatures found for user {}", user_pubkey);
-                return Pnl::default();
+    // 汇总该 mint 下所有代币账户以及 owner 钱包本身的签名（后者用于覆盖已关闭 ATA 上的交易），去重后合并为一份历史
+    pub async fn get_merged_signatures(
+        &self,
+        user_pubkey: &Pubkey,
+        token_accounts: &[Pubkey],
+        bound: SignatureBound,
+    ) -> Result<SignatureHistory, IndexerError> {
+        let mut merged: Option<SignatureHistory> = None;
+        let mut last_error = None;
+        // 被 max_signatures 截断的地址只覆盖到其最旧的签名，更早的区间在该地址上是缺失的
+        let mut covered_since = None;
+
+        for address in token_accounts.iter().chain(std::iter::once(user_pubkey)) {
+            match self.get_transaction_signatures(address, bound).await {
+                Ok(history) => {
+                    if !history.window.complete {
+                        covered_since = cmp::max(covered_since, history.window.oldest_block_time);
+                    }
+                    match merged.as_mut() {
+                        Some(merged) => merged.merge(history),
+                        None => merged = Some(history),
+                    }
+                },
+                Err(e) => {
+                    eprintln!("No signatures fetched for address {}: {}", address, e);
+                    last_error = Some(e);
+                }
             }
         }
+        // max_signatures 作用于合并后的总数，而不是每个地址
+        if let Some(merged) = merged.as_mut() {
+            merged.truncate(covered_since, bound.max_signatures);
+        }
 
+        match (merged, last_error) {
+            // 部分地址获取失败时，合并结果不再是完整历史
+            (Some(mut merged), Some(_)) => {
+                merged.window.complete = false;
+                Ok(merged)
+            },
+            (Some(merged), None) => Ok(merged),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(IndexerError::NoTransactions(user_pubkey.to_string())),
+        }
     }
 
     pub async fn get_transaction_signatures(
         &self,
-        user_pubkey: &Pubkey,
-    ) -> Option<Vec<Signature>> {
-        // 由于速率限制等因素，避免过长无反应，最多查找100个
-        let config = GetConfirmedSignaturesForAddress2Config {
-            limit: Some(25),// 指定返回的交易签名的最大数量，None表示不限制
-            before: None,// 指定一个交易签名，返回的结果将是该签名之前的交易签名
-            until: None,// 指定一个交易签名，返回的结果将是该签名之后的交易签名
-            commitment: Some(CommitmentConfig::finalized()),// 指定查询的确认级别
-            //min_context_slot: None,// 区域为指定slot到目前slot，None表示不限制
-        };
+        address: &Pubkey,
+        bound: SignatureBound,
+    ) -> Result<SignatureHistory, IndexerError> {
+        let mut signatures = vec![];
+        let mut block_times = HashMap::new();
+        let mut window = SignatureWindow::default();
+        let mut before = None;
+
+        // 通过 before 游标逐页向前翻，直到历史耗尽或触达调用方给定的深度/时间边界
+        loop {
+            let limit = match bound.max_signatures {
+                Some(max) => cmp::min(max - signatures.len(), SIGNATURE_PAGE_SIZE),
+                None => SIGNATURE_PAGE_SIZE,
+            };
+            if limit == 0 {
+                break;
+            }
 
-        let signatures = self.client.inner.get_signatures_for_address_with_config(user_pubkey, config);
+            let config = GetConfirmedSignaturesForAddress2Config {
+                limit: Some(limit),// 指定返回的交易签名的最大数量
+                before,// 指定一个交易签名，返回的结果将是该签名之前的交易签名
+                until: None,// 指定一个交易签名，返回的结果将是该签名之后的交易签名
+                commitment: Some(self.commitment),// 指定查询的确认级别
+            };
+
+            let page = match self.client.inner.get_signatures_for_address_with_config(address, config).await {
+                Ok(page) => page,
+                Err(e) => {
+                    // 第一页就失败则视为无法获取；否则返回已获取的部分（window.complete 为 false）
+                    if signatures.is_empty() {
+                        return Err(IndexerError::Rpc(format!("Error fetching signatures for {}: {}", address, e)));
+                    }
+                    eprintln!("Error fetching signatures: {}", e);
+                    break;
+                }
+            };
+            let page_len = page.len();
+
+            for status in page {
+                if let (Some(since), Some(block_time)) = (bound.since, status.block_time)
+                    && block_time < since
+                {
+                    window.complete = true;
+                    break;
+                }
+                let Ok(signature) = Signature::from_str(&status.signature) else {
+                    continue;
+                };
+                if window.newest_signature.is_none() {
+                    window.newest_signature = Some(status.signature.clone());
+                    window.newest_block_time = status.block_time;
+                }
+                window.oldest_signature = Some(status.signature);
+                window.oldest_block_time = status.block_time.or(window.oldest_block_time);
+                if let Some(block_time) = status.block_time {
+                    block_times.insert(signature, block_time);
+                }
+                signatures.push(signature);
+            }
 
-        match signatures {
-            Ok(signatures) => {
-                Some(signatures.into_iter().map(|s| Signature::from_str(&s.signature).unwrap_or(Signature::default())).collect())
-            },
-            Err(e) => {
-                eprintln!("Error fetching signatures: {}", e);
-                None
+            if window.complete {
+                break;
+            }
+            if page_len < limit {
+                window.complete = true;
+                break;
             }
+            before = signatures.last().copied();
         }
+
+        window.signature_count = signatures.len();
+
+        #[cfg(test)]
+        println!("Signature window for {}: {:?}", address, window);
+
+        Ok(SignatureHistory { signatures, window, block_times })
     }
 } 
 
@@ -241,11 +688,98 @@ mod tests {
 
     #[tokio::test(flavor = "multi_thread")]
     async fn test_get_jupiter_v6_txs() {
-        let indexer = JupiterV6Indexer::new();//DMoie6GXkodFQYp2MDf5eBj48GR9F9AWHBnT9jZ7g1zC
+        let indexer = JupiterV6Indexer::new(Arc::new(Config::default()));//DMoie6GXkodFQYp2MDf5eBj48GR9F9AWHBnT9jZ7g1zC
         let user_pubkey = Pubkey::from_str("J14Cg556roeBSgWFEKNTiSQeydMPRW6FZNB2zDMmSadQ").expect("Failed to parse user public key");
         let token_pubkey = Pubkey::from_str("KMNo3nJsBXfcpJTVhZcXLW7RmTwTt4GVFE7suUBo9sS").expect("Failed to parse user public key");
 
-        let res = indexer.get_jupiter_v6_txs(&user_pubkey, &token_pubkey).await;
+        let res = indexer.get_jupiter_v6_txs(&user_pubkey, &token_pubkey, SignatureBound { max_signatures: Some(25), since: None }, CostBasisPolicy::default()).await;
         println!("Result: {:?}", res);
     }
+
+    #[test]
+    fn test_merge_signature_history() {
+        let a = Signature::from([1u8; 64]);
+        let b = Signature::from([2u8; 64]);
+        let c = Signature::from([3u8; 64]);
+
+        let mut history = SignatureHistory {
+            signatures: vec![a, b],
+            window: SignatureWindow {
+                signature_count: 2,
+                newest_signature: Some(a.to_string()),
+                newest_block_time: Some(200),
+                oldest_signature: Some(b.to_string()),
+                oldest_block_time: Some(150),
+                complete: true,
+            },
+            block_times: HashMap::from([(a, 200), (b, 150)]),
+        };
+        history.merge(SignatureHistory {
+            signatures: vec![b, c],
+            window: SignatureWindow {
+                signature_count: 2,
+                newest_signature: Some(b.to_string()),
+                newest_block_time: Some(150),
+                oldest_signature: Some(c.to_string()),
+                oldest_block_time: Some(100),
+                complete: false,
+            },
+            block_times: HashMap::from([(b, 150), (c, 100)]),
+        });
+
+        assert_eq!(history.signatures, vec![a, b, c]);
+        assert_eq!(history.window.signature_count, 3);
+        assert_eq!(history.window.newest_signature, Some(a.to_string()));
+        assert_eq!(history.window.oldest_signature, Some(c.to_string()));
+        assert_eq!(history.window.oldest_block_time, Some(100));
+        assert!(!history.window.complete);
+    }
+
+    fn history(entries: &[(u8, i64)], complete: bool) -> SignatureHistory {
+        let signatures = entries.iter().map(|(id, _)| Signature::from([*id; 64])).collect::<Vec<_>>();
+        let block_times = signatures.iter().zip(entries).map(|(sig, (_, block_time))| (*sig, *block_time)).collect();
+        SignatureHistory {
+            window: SignatureWindow {
+                signature_count: signatures.len(),
+                newest_signature: signatures.first().map(Signature::to_string),
+                newest_block_time: entries.first().map(|(_, block_time)| *block_time),
+                oldest_signature: signatures.last().map(Signature::to_string),
+                oldest_block_time: entries.last().map(|(_, block_time)| *block_time),
+                complete,
+            },
+            signatures,
+            block_times,
+        }
+    }
+
+    #[test]
+    fn test_truncate_merged_history() {
+        // 代币账户交易频繁，按 max_signatures=3 截断在 280；钱包本身的历史是完整的
+        let token_account = history(&[(1, 300), (2, 290), (3, 280)], false);
+        let wallet = history(&[(4, 295), (5, 250), (6, 200)], true);
+
+        let mut merged = token_account.clone();
+        merged.merge(wallet.clone());
+        merged.truncate(Some(280), Some(3));
+        let ids = merged.signatures.iter().map(|sig| sig.as_ref()[0]).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 4, 2]);
+        assert_eq!(merged.window.signature_count, 3);
+        assert_eq!(merged.window.newest_block_time, Some(300));
+        assert_eq!(merged.window.oldest_block_time, Some(290));
+        assert!(!merged.window.complete);
+
+        // 不限数量时仍只保留代币账户已覆盖的区间，250 与 200 在代币账户上可能有缺失
+        let mut merged = token_account;
+        merged.merge(wallet.clone());
+        merged.truncate(Some(280), None);
+        let ids = merged.signatures.iter().map(|sig| sig.as_ref()[0]).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 4, 2, 3]);
+        assert_eq!(merged.window.oldest_block_time, Some(280));
+
+        // 完整的历史不受影响
+        let mut merged = wallet;
+        merged.truncate(None, None);
+        assert_eq!(merged.window.signature_count, 3);
+        assert!(merged.window.complete);
+    }
 }
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+use spl_token_2022::extension::StateWithExtensions;
+use spl_token_2022::state::Mint;
+
+pub const METAPLEX_METADATA_PROGRAM_ID: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+pub const SPL_TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGCPFdR3aeNhbhQjWR4CZn5";
+
+// getMultipleAccounts 单次最多 100 个账户，每个 mint 需要 mint 账户与 metadata 账户两个
+const MINTS_PER_REQUEST: usize = 50;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MintMetadata {
+    pub decimals: u8,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+}
+
+// mint 的精度与名称在创建后基本不变，进程内缓存，所有请求共享
+#[derive(Clone, Default)]
+pub struct MintMetadataCache {
+    cache: Arc<Mutex<HashMap<Pubkey, MintMetadata>>>,
+}
+
+impl MintMetadataCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 返回所有能解析的 mint 的信息，未缓存的从链上读取，读取失败的不在结果中
+    pub async fn resolve(
+        &self,
+        client: &RpcClient,
+        mints: impl IntoIterator<Item = Pubkey>,
+    ) -> HashMap<Pubkey, MintMetadata> {
+        let mut resolved = HashMap::new();
+        let mut missing = vec![];
+        {
+            let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            for mint in mints {
+                match cache.get(&mint) {
+                    Some(metadata) => {
+                        resolved.insert(mint, metadata.clone());
+                    },
+                    None if !missing.contains(&mint) => missing.push(mint),
+                    None => {},
+                }
+            }
+        }
+
+        for chunk in missing.chunks(MINTS_PER_REQUEST) {
+            let keys = chunk
+                .iter()
+                .flat_map(|mint| [*mint, metadata_address(mint)])
+                .collect::<Vec<_>>();
+            let accounts = match client.get_multiple_accounts(&keys).await {
+                Ok(accounts) => accounts,
+                Err(e) => {
+                    eprintln!("Error fetching mint accounts: {}", e);
+                    continue;
+                }
+            };
+
+            let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            for (mint, pair) in chunk.iter().zip(accounts.chunks(2)) {
+                let [mint_account, metadata_account] = pair else {
+                    continue;
+                };
+                let Some(decimals) = mint_account.as_ref().and_then(mint_decimals) else {
+                    eprintln!("Failed to read decimals of mint {}", mint);
+                    continue;
+                };
+                let (name, symbol) = metadata_account
+                    .as_ref()
+                    .and_then(|account| parse_metadata(&account.data))
+                    .unzip();
+                let metadata = MintMetadata { decimals, name, symbol };
+                cache.insert(*mint, metadata.clone());
+                resolved.insert(*mint, metadata);
+            }
+        }
+
+        resolved
+    }
+}
+
+// Metaplex metadata 账户地址：PDA ["metadata", program_id, mint]
+pub fn metadata_address(mint: &Pubkey) -> Pubkey {
+    let program_id = Pubkey::from_str_const(METAPLEX_METADATA_PROGRAM_ID);
+    Pubkey::find_program_address(&[b"metadata", program_id.as_ref(), mint.as_ref()], &program_id).0
+}
+
+// 经典 SPL Token 与 Token-2022 的 mint 基础布局相同
+pub fn mint_decimals(account: &Account) -> Option<u8> {
+    if account.owner != Pubkey::from_str_const(SPL_TOKEN_PROGRAM_ID) && account.owner != spl_token_2022::id() {
+        return None;
+    }
+    let mint = StateWithExtensions::<Mint>::unpack(&account.data).ok()?;
+    Some(mint.base.decimals)
+}
+
+// metadata 账户布局：key(1) + update_authority(32) + mint(32) + name(borsh string) + symbol(borsh string) + ...
+// name 与 symbol 以 \0 补齐到固定长度
+pub fn parse_metadata(data: &[u8]) -> Option<(String, String)> {
+    let mut rest = data.get(65..)?;
+    let mut read_string = || {
+        let len = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let value = rest.get(4..4 + len)?;
+        rest = &rest[4 + len..];
+        Some(String::from_utf8_lossy(value).trim_end_matches('\0').trim().to_string())
+    };
+    let name = read_string()?;
+    let symbol = read_string()?;
+    Some((name, symbol))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn borsh_string(value: &str, padded: usize) -> Vec<u8> {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize(padded, 0);
+        let mut data = (bytes.len() as u32).to_le_bytes().to_vec();
+        data.extend_from_slice(&bytes);
+        data
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let mut data = vec![4u8];
+        data.extend_from_slice(&[0u8; 64]);
+        data.extend_from_slice(&borsh_string("Kamino", 32));
+        data.extend_from_slice(&borsh_string("KMNO", 10));
+        data.extend_from_slice(&borsh_string("https://example.com", 200));
+
+        assert_eq!(parse_metadata(&data), Some(("Kamino".to_string(), "KMNO".to_string())));
+        assert_eq!(parse_metadata(&data[..70]), None);
+    }
+
+    #[test]
+    fn test_mint_decimals() {
+        use solana_sdk::program_pack::Pack;
+
+        let mut data = vec![0u8; Mint::LEN];
+        Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Mint::default()
+        }
+        .pack_into_slice(&mut data);
+        let account = Account {
+            owner: Pubkey::from_str_const(SPL_TOKEN_PROGRAM_ID),
+            data,
+            ..Account::default()
+        };
+        assert_eq!(mint_decimals(&account), Some(6));
+
+        // 不属于 token 程序的账户
+        let account = Account { owner: Pubkey::new_unique(), ..account };
+        assert_eq!(mint_decimals(&account), None);
+    }
+}
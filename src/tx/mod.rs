@@ -1,3 +1,13 @@
 pub mod jupiterv6_indexer;
 pub mod inner_tx;
-pub mod post_balance;
\ No newline at end of file
+pub mod post_balance;
+pub mod batch_rpc;
+pub mod rpc_middleware;
+pub mod rpc_pool;
+pub mod coverage;
+pub mod network_fee;
+pub mod balance_delta;
+pub mod swap_decoder;
+pub mod decoders;
+pub mod token_2022;
+pub mod mint_metadata;
\ No newline at end of file
@@ -0,0 +1,67 @@
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::{EncodedTransaction, EncodedTransactionWithStatusMeta};
+
+use crate::tx::balance_delta;
+
+// 网络费用以 lamports 计，1 SOL = 10^9 lamports
+pub const SOL_DECIMALS: u8 = 9;
+
+// 交易的手续费支付者，即第一个账户
+pub fn fee_payer(transaction: &EncodedTransaction) -> Option<Pubkey> {
+    balance_delta::account_keys(transaction).first().copied()
+}
+
+// 用户为该交易支付的网络费用（基础费用 + 优先费，单位 lamports），由其他账户代付（如 gasless 交易）时为 0
+pub fn paid_fee(
+    user: &Pubkey,
+    transaction: &EncodedTransactionWithStatusMeta,
+) -> u64 {
+    let Some(meta) = transaction.meta.as_ref() else {
+        return 0;
+    };
+    if fee_payer(&transaction.transaction) == Some(*user) {
+        meta.fee
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "J14Cg556roeBSgWFEKNTiSQeydMPRW6FZNB2zDMmSadQ";
+    const RELAYER: &str = "KMNo3nJsBXfcpJTVhZcXLW7RmTwTt4GVFE7suUBo9sS";
+
+    fn transaction(fee_payer: &str, fee: u64) -> EncodedTransactionWithStatusMeta {
+        serde_json::from_value(serde_json::json!({
+            "transaction": {
+                "signatures": [],
+                "message": {
+                    "accountKeys": [
+                        { "pubkey": fee_payer, "writable": true, "signer": true, "source": "transaction" },
+                        { "pubkey": USER, "writable": true, "signer": true, "source": "transaction" }
+                    ],
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": []
+                }
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": fee,
+                "preBalances": [],
+                "postBalances": []
+            }
+        }))
+        .expect("Failed to build transaction")
+    }
+
+    #[test]
+    fn test_paid_fee() {
+        let user = Pubkey::from_str_const(USER);
+        assert_eq!(paid_fee(&user, &transaction(USER, 105_000)), 105_000);
+        // 由 relayer 代付手续费
+        assert_eq!(paid_fee(&user, &transaction(RELAYER, 105_000)), 0);
+    }
+}
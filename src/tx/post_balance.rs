@@ -10,33 +10,121 @@ pub struct UserBalanceInfo {
     pub decimals: u8,
 }
 
+// 用户在该交易中持有的代币信息，以 post balances 为准；本交易中被关闭的账户只出现在 pre balances 中，余额记为 0
 pub fn parse_balance(
     user: &Pubkey,
     meta: &solana_transaction_status::UiTransactionStatusMeta,
 ) -> Vec<UserBalanceInfo> {
     let mut user_balances: Vec<UserBalanceInfo> = vec![];
-    
-    match meta.post_token_balances.as_ref() {
-        OptionSerializer::Some(post_balances) => {
-            for balance in post_balances {
-                if let OptionSerializer::Some(this_user) = balance.owner.as_ref() {
-                    if Pubkey::from_str_const(this_user) == *user {
-                        user_balances.push(
-                            UserBalanceInfo {
-                                user: Pubkey::from_str_const(&this_user),
-                                balance: balance.ui_token_amount.amount.parse::<u64>().unwrap_or(0),
-                                mint: Pubkey::from_str_const(&balance.mint),
-                                decimals: balance.ui_token_amount.decimals,
-                            }
-                        );
-                    }
+
+    for (balances, closed) in [(meta.post_token_balances.as_ref(), false), (meta.pre_token_balances.as_ref(), true)] {
+        let OptionSerializer::Some(balances) = balances else {
+            println!("No token balances found for user {}", user);
+            continue;
+        };
+        for balance in balances {
+            if let OptionSerializer::Some(this_user) = balance.owner.as_ref()
+                && Pubkey::from_str_const(this_user) == *user
+            {
+                let mint = Pubkey::from_str_const(&balance.mint);
+                if closed && user_balances.iter().any(|info| info.mint == mint) {
+                    continue;
                 }
+                user_balances.push(
+                    UserBalanceInfo {
+                        user: Pubkey::from_str_const(this_user),
+                        balance: if closed { 0 } else { balance.ui_token_amount.amount.parse::<u64>().unwrap_or(0) },
+                        mint,
+                        decimals: balance.ui_token_amount.decimals,
+                    }
+                );
             }
-        },
-        _ => {
-            println!("No post token balances found for user {}", user);
         }
     }
 
     user_balances
+}
+
+// 交易的 pre/post token balances 中是否存在该用户持有该 mint 的记录（包括本交易中被关闭的账户）
+pub fn references_mint(
+    user: &Pubkey,
+    mint: &Pubkey,
+    meta: &solana_transaction_status::UiTransactionStatusMeta,
+) -> bool {
+    let user = user.to_string();
+    let mint = mint.to_string();
+
+    [meta.pre_token_balances.as_ref(), meta.post_token_balances.as_ref()]
+        .into_iter()
+        .filter_map(|balances| match balances {
+            OptionSerializer::Some(balances) => Some(balances),
+            _ => None,
+        })
+        .flatten()
+        .any(|balance| {
+            balance.mint == mint
+                && matches!(balance.owner.as_ref(), OptionSerializer::Some(owner) if *owner == user)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "J14Cg556roeBSgWFEKNTiSQeydMPRW6FZNB2zDMmSadQ";
+    const MINT: &str = "KMNo3nJsBXfcpJTVhZcXLW7RmTwTt4GVFE7suUBo9sS";
+
+    fn token_balance(mint: &str, owner: &str) -> serde_json::Value {
+        serde_json::json!({
+            "accountIndex": 1,
+            "mint": mint,
+            "owner": owner,
+            "uiTokenAmount": {
+                "uiAmount": 1.0,
+                "decimals": 6,
+                "amount": "1000000",
+                "uiAmountString": "1"
+            }
+        })
+    }
+
+    fn meta(pre: Vec<serde_json::Value>, post: Vec<serde_json::Value>) -> solana_transaction_status::UiTransactionStatusMeta {
+        serde_json::from_value(serde_json::json!({
+            "err": null,
+            "status": { "Ok": null },
+            "fee": 5000,
+            "preBalances": [],
+            "postBalances": [],
+            "preTokenBalances": pre,
+            "postTokenBalances": post,
+        }))
+        .expect("Failed to build meta")
+    }
+
+    #[test]
+    fn test_parse_balance_includes_closed_accounts() {
+        let user = Pubkey::from_str_const(USER);
+        let other_mint = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+        let balances = parse_balance(&user, &meta(
+            vec![token_balance(MINT, USER), token_balance(other_mint, USER)],
+            vec![token_balance(other_mint, USER)],
+        ));
+        assert_eq!(balances.len(), 2);
+        assert_eq!(balances[0].balance, 1_000_000);
+        assert_eq!(balances[1].mint, Pubkey::from_str_const(MINT));
+        assert_eq!(balances[1].balance, 0);
+    }
+
+    #[test]
+    fn test_references_mint() {
+        let user = Pubkey::from_str_const(USER);
+        let mint = Pubkey::from_str_const(MINT);
+        let other_mint = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+        // 账户在本交易中被关闭，只出现在 pre balances 中
+        assert!(references_mint(&user, &mint, &meta(vec![token_balance(MINT, USER)], vec![])));
+        assert!(references_mint(&user, &mint, &meta(vec![], vec![token_balance(MINT, USER)])));
+        assert!(!references_mint(&user, &mint, &meta(vec![token_balance(other_mint, USER)], vec![])));
+        assert!(!references_mint(&user, &mint, &meta(vec![token_balance(MINT, other_mint)], vec![])));
+    }
 }
\ No newline at end of file
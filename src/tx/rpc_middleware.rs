@@ -0,0 +1,440 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use async_trait::async_trait;
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, StatusCode};
+use serde_json::{json, Value};
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_custom_error::{
+    JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE, JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET,
+    JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_UNREACHABLE, JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED,
+    JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+};
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use crate::config::app_config::RpcConfig;
+use crate::tx::rpc_pool::{EndpointMetrics, EndpointOutcome, EndpointPool};
+
+// JSON-RPC 内部错误，多为节点临时故障
+const JSON_RPC_INTERNAL_ERROR: i64 = -32603;
+// 部分 RPC 提供商以 JSON-RPC error code 的形式返回限流
+const JSON_RPC_RATE_LIMITED: i64 = 429;
+// Retry-After 超过该值时按退避策略处理，避免单个请求挂起过久
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+// 令牌桶：最多积攒 burst 个令牌，每秒补充 rate 个
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            rate,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    // 等待直到拿到一个令牌，返回等待的时长
+    pub async fn acquire(&self) -> Duration {
+        let start = Instant::now();
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let (tokens, last) = &mut *state;
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate).min(self.burst);
+                *last = now;
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return start.elapsed();
+                }
+                Duration::from_secs_f64((1.0 - *tokens) / self.rate)
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+// 指数退避 + full jitter
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let cap = self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        Duration::from_millis(rand::thread_rng().gen_range(0..=cap.as_millis() as u64))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcMiddlewareError {
+    Permanent { code: Option<i64>, message: String },// 重试无意义的错误，例如参数错误、4xx
+    Exhausted(String),// 可重试的错误在达到重试上限后仍失败
+}
+
+impl std::fmt::Display for RpcMiddlewareError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcMiddlewareError::Permanent { code: Some(code), message } => write!(f, "RPC error {}: {}", code, message),
+            RpcMiddlewareError::Permanent { code: None, message } => write!(f, "{}", message),
+            RpcMiddlewareError::Exhausted(message) => write!(f, "Retries exhausted: {}", message),
+        }
+    }
+}
+
+// 单次请求的结果分类
+enum Attempt {
+    Done(Value),
+    Retry { message: String, retry_after: Option<Duration>, rate_limited: bool },
+    Fail(RpcMiddlewareError),
+}
+
+impl Attempt {
+    fn endpoint_outcome(&self) -> EndpointOutcome {
+        match self {
+            Attempt::Done(_) => EndpointOutcome::Success,
+            // 永久错误是请求本身的问题（参数错误等），不计入端点的成功或失败
+            Attempt::Fail(_) => EndpointOutcome::Rejected,
+            Attempt::Retry { rate_limited: true, retry_after, .. } => {
+                EndpointOutcome::RateLimited(retry_after.map(|retry_after| retry_after.min(MAX_RETRY_AFTER)))
+            },
+            Attempt::Retry { .. } => EndpointOutcome::Failure,
+        }
+    }
+}
+
+// 所有 indexer 的 RPC 调用（RpcClient 以及 batch 请求）共用的 HTTP 层：多端点负载均衡与故障转移、限流、退避重试、429 处理
+#[derive(Clone)]
+pub struct RpcMiddleware {
+    http: reqwest::Client,
+    pool: Arc<EndpointPool>,
+    policy: RetryPolicy,
+    request_id: Arc<AtomicU64>,
+    stats: Arc<RwLock<RpcTransportStats>>,
+}
+
+impl RpcMiddleware {
+    pub fn new(config: &RpcConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .expect("Failed to build http client");
+
+        RpcMiddleware {
+            http,
+            pool: Arc::new(EndpointPool::new(config)),
+            policy: RetryPolicy {
+                max_retries: config.retries,
+                base_delay: Duration::from_millis(config.backoff_base_ms),
+                max_delay: Duration::from_millis(config.backoff_max_ms),
+            },
+            request_id: Arc::new(AtomicU64::new(0)),
+            stats: Arc::new(RwLock::new(RpcTransportStats::default())),
+        }
+    }
+
+    pub fn metrics(&self) -> Vec<EndpointMetrics> {
+        self.pool.metrics()
+    }
+
+    // 发送任意 JSON-RPC 请求体（单个或 batch），返回节点的原始响应
+    pub async fn send_json(&self, body: &Value) -> Result<Value, RpcMiddlewareError> {
+        let start = Instant::now();
+        let mut rate_limited_time = Duration::ZERO;
+        let mut attempt = 0;
+        // 本轮已经失败过的端点；故障转移不等待、不消耗重试次数，所有端点都失败后才退避并开始新一轮
+        let mut tried = vec![];
+        let mut next = None;
+
+        let result = loop {
+            let index = next.take().unwrap_or_else(|| self.pool.select());
+            let endpoint = self.pool.endpoint(index);
+            rate_limited_time += endpoint.limiter.acquire().await;
+
+            let started = Instant::now();
+            let outcome = self.send_once(&endpoint.url, body).await;
+            self.pool.record(index, outcome.endpoint_outcome(), started.elapsed());
+
+            match outcome {
+                Attempt::Done(value) => break Ok(value),
+                Attempt::Fail(e) => break Err(e),
+                Attempt::Retry { message, retry_after, .. } => {
+                    tried.push(index);
+                    // 带 Retry-After 的限流端点已进入冷却，不会再被选中
+                    if let Some(index) = self.pool.select_excluding(&tried) {
+                        #[cfg(test)]
+                        eprintln!("Failing over from {}: {}", endpoint.url, message);
+
+                        next = Some(index);
+                        continue;
+                    }
+                    if attempt >= self.policy.max_retries {
+                        break Err(RpcMiddlewareError::Exhausted(message));
+                    }
+                    attempt += 1;
+                    tried.clear();
+                    let delay = match retry_after {
+                        Some(retry_after) if retry_after <= MAX_RETRY_AFTER => retry_after,
+                        _ => self.policy.backoff(attempt - 1),
+                    };
+
+                    #[cfg(test)]
+                    eprintln!("Retrying RPC request in {:?}: {}", delay, message);
+
+                    if retry_after.is_some() {
+                        rate_limited_time += delay;
+                    }
+                    sleep(delay).await;
+                }
+            }
+        };
+
+        if let Ok(mut stats) = self.stats.write() {
+            stats.request_count += 1;
+            stats.elapsed_time += start.elapsed();
+            stats.rate_limited_time += rate_limited_time;
+        }
+        result
+    }
+
+    async fn send_once(&self, url: &str, body: &Value) -> Attempt {
+        let res = match self.http.post(url).json(body).send().await {
+            Ok(res) => res,
+            // 超时、连接失败等网络错误均可重试
+            Err(e) => return Attempt::Retry { message: e.to_string(), retry_after: None, rate_limited: false },
+        };
+
+        let status = res.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = res.headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| parse_retry_after(value, SystemTime::now()));
+            return Attempt::Retry { message: format!("HTTP {}", status), retry_after, rate_limited: true };
+        }
+        // 鉴权失败、地址错误多为该端点的配置问题（key 失效、路径错误），计为端点失败并换一个端点重试
+        if status.is_server_error()
+            || matches!(
+                status,
+                StatusCode::UNAUTHORIZED
+                    | StatusCode::FORBIDDEN
+                    | StatusCode::NOT_FOUND
+                    | StatusCode::METHOD_NOT_ALLOWED
+                    | StatusCode::PROXY_AUTHENTICATION_REQUIRED
+            )
+        {
+            return Attempt::Retry { message: format!("HTTP {}", status), retry_after: None, rate_limited: false };
+        }
+        if !status.is_success() {
+            return Attempt::Fail(RpcMiddlewareError::Permanent {
+                code: Some(i64::from(status.as_u16())),
+                message: format!("HTTP {}", status),
+            });
+        }
+
+        let value = match res.json::<Value>().await {
+            Ok(value) => value,
+            Err(e) => return Attempt::Retry { message: format!("Invalid response: {}", e), retry_after: None, rate_limited: false },
+        };
+
+        // batch 响应为数组，逐条的错误交给调用方处理
+        match value.get("error") {
+            Some(error) if error.is_object() => {
+                let code = error.get("code").and_then(Value::as_i64);
+                let message = error.get("message").and_then(Value::as_str).unwrap_or_default().to_string();
+                if code.is_some_and(is_retriable_code) {
+                    Attempt::Retry {
+                        message: format!("RPC error {:?}: {}", code, message),
+                        retry_after: None,
+                        rate_limited: code == Some(JSON_RPC_RATE_LIMITED),
+                    }
+                } else {
+                    Attempt::Fail(RpcMiddlewareError::Permanent { code, message })
+                }
+            },
+            _ => Attempt::Done(value),
+        }
+    }
+}
+
+// Retry-After 可以是秒数或 HTTP 日期，已过去的日期视为立即重试
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+pub fn is_retriable_code(code: i64) -> bool {
+    matches!(
+        code,
+        JSON_RPC_RATE_LIMITED
+            | JSON_RPC_INTERNAL_ERROR
+            | JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+            | JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+            | JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET
+            | JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED
+            | JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_UNREACHABLE
+    )
+}
+
+impl From<RpcMiddlewareError> for ClientError {
+    fn from(e: RpcMiddlewareError) -> Self {
+        match e {
+            RpcMiddlewareError::Permanent { code: Some(code), message } => RpcError::RpcResponseError {
+                code,
+                message,
+                data: RpcResponseErrorData::Empty,
+            }.into(),
+            e => ClientErrorKind::Custom(e.to_string()).into(),
+        }
+    }
+}
+
+// 让 nonblocking RpcClient 的所有请求都经过该中间件
+#[async_trait]
+impl RpcSender for RpcMiddleware {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: Value,
+    ) -> ClientResult<Value> {
+        let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let body = request.build_request_json(request_id, params);
+        let mut response = self.send_json(&body).await?;
+        Ok(response
+            .get_mut("result")
+            .map(Value::take)
+            .unwrap_or(json!(null)))
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.stats.read().map(|stats| stats.clone()).unwrap_or_default()
+    }
+
+    fn url(&self) -> String {
+        self.pool.endpoint(0).url.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1_000),
+        };
+        for attempt in 0..10 {
+            let cap = Duration::from_millis(100 * 2u64.pow(attempt)).min(policy.max_delay);
+            assert!(policy.backoff(attempt) <= cap);
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = httpdate::parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        assert_eq!(parse_retry_after(" 5 ", now), Some(Duration::from_secs(5)));
+        assert_eq!(parse_retry_after("Sun, 06 Nov 1994 08:50:07 GMT", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Sun, 06 Nov 1994 08:49:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn test_retriable_codes() {
+        assert!(is_retriable_code(JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY));
+        assert!(is_retriable_code(JSON_RPC_RATE_LIMITED));
+        // 参数错误、slot 被跳过等重试无意义
+        assert!(!is_retriable_code(-32602));
+        assert!(!is_retriable_code(-32007));
+    }
+
+    // 本地起两个 RPC 端点：第一个始终限流，第二个正常返回
+    // 本地起一个 RPC 端点，每次请求都返回给定的状态码与响应体（429 时带 Retry-After）
+    async fn serve(status: StatusCode, body: Value) -> String {
+        use axum::http::header::RETRY_AFTER;
+        use axum::{routing::post, Json, Router};
+
+        let app = Router::new().route("/", post(move || async move {
+            (status, [(RETRY_AFTER, "30")], Json(body))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn ok() -> Value {
+        json!({"jsonrpc": "2.0", "id": 0, "result": "ok"})
+    }
+
+    #[tokio::test]
+    async fn test_failover_with_unequal_weights() {
+        let config = RpcConfig {
+            urls: vec![serve(StatusCode::TOO_MANY_REQUESTS, json!({})).await, serve(StatusCode::OK, ok()).await],
+            weights: vec![3, 1],
+            retries: 0,
+            ..RpcConfig::default()
+        };
+        let middleware = RpcMiddleware::new(&config);
+
+        // retries=0 时仍会转移到另一个端点；限流端点按 Retry-After 冷却，之后的请求直接选择健康端点
+        for _ in 0..3 {
+            let response = middleware.send_json(&json!({"jsonrpc": "2.0", "id": 0, "method": "getSlot"})).await.unwrap();
+            assert_eq!(response["result"], "ok");
+        }
+        let metrics = middleware.metrics();
+        assert_eq!(metrics[0].rate_limited, 1);
+        assert!(!metrics[0].healthy);
+        assert_eq!(metrics[1].successes, 3);
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_outcomes() {
+        // 第一个端点的 key 失效，第二个端点拒绝请求参数
+        let invalid_params = json!({"jsonrpc": "2.0", "id": 0, "error": {"code": -32602, "message": "Invalid params"}});
+        let config = RpcConfig {
+            urls: vec![serve(StatusCode::FORBIDDEN, json!({})).await, serve(StatusCode::OK, invalid_params).await],
+            retries: 0,
+            ..RpcConfig::default()
+        };
+        let middleware = RpcMiddleware::new(&config);
+
+        let response = middleware.send_json(&json!({"jsonrpc": "2.0", "id": 0, "method": "getSlot"})).await;
+        assert!(matches!(response, Err(RpcMiddlewareError::Permanent { code: Some(-32602), .. })));
+        let metrics = middleware.metrics();
+        assert_eq!((metrics[0].failures, metrics[0].successes), (1, 0));
+        // 参数错误不计入端点的成功或失败
+        assert_eq!((metrics[1].failures, metrics[1].successes, metrics[1].rejected), (0, 0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_waits_for_refill() {
+        let limiter = RateLimiter::new(20.0, 2);
+        assert!(limiter.acquire().await < Duration::from_millis(10));
+        assert!(limiter.acquire().await < Duration::from_millis(10));
+        // 令牌耗尽后需要等待约 1/20 秒
+        assert!(limiter.acquire().await >= Duration::from_millis(30));
+    }
+}
@@ -0,0 +1,270 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::app_config::RpcConfig;
+use crate::tx::rpc_middleware::RateLimiter;
+
+// 单次请求对端点健康度的影响
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointOutcome {
+    Success,
+    RateLimited(Option<Duration>),// HTTP 429，不计入连续失败；带 Retry-After 时在该时长内不再选择该端点
+    Failure,// 超时、5xx、鉴权失败、节点不健康等
+    Rejected,// 请求本身被拒绝（参数错误等），与端点健康度无关
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointMetrics {
+    pub url: String,
+    pub weight: u32,
+    pub healthy: bool,
+    pub requests: u64,
+    pub successes: u64,
+    pub failures: u64,
+    pub rate_limited: u64,
+    pub rejected: u64,
+    pub consecutive_failures: u32,
+    pub average_latency_ms: f64,
+}
+
+#[derive(Default)]
+struct EndpointState {
+    current_weight: i64,// 平滑加权轮询的当前权重
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+    requests: u64,
+    successes: u64,
+    failures: u64,
+    rate_limited: u64,
+    rejected: u64,
+    total_latency: Duration,
+}
+
+pub struct Endpoint {
+    pub url: String,
+    pub weight: u32,
+    pub limiter: RateLimiter,// 限流按端点计算，各 RPC 提供商的额度相互独立
+}
+
+// 多个 RPC 端点：平滑加权轮询 + 连续失败后摘除一段时间
+pub struct EndpointPool {
+    endpoints: Vec<Endpoint>,
+    states: Mutex<Vec<EndpointState>>,
+    unhealthy_threshold: u32,
+    cooldown: Duration,
+}
+
+impl EndpointPool {
+    pub fn new(config: &RpcConfig) -> Self {
+        let endpoints = config.urls
+            .iter()
+            .enumerate()
+            .map(|(i, url)| Endpoint {
+                url: url.clone(),
+                weight: config.weights.get(i).copied().unwrap_or(1).max(1),
+                limiter: RateLimiter::new(config.requests_per_second, config.burst),
+            })
+            .collect::<Vec<_>>();
+        let states = endpoints.iter().map(|_| EndpointState::default()).collect();
+
+        EndpointPool {
+            endpoints,
+            states: Mutex::new(states),
+            unhealthy_threshold: config.unhealthy_threshold.max(1),
+            cooldown: Duration::from_secs(config.cooldown_secs),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    pub fn endpoint(&self, index: usize) -> &Endpoint {
+        &self.endpoints[index]
+    }
+
+    // nginx 的平滑加权轮询：只在健康端点中选择，全部不健康时退化为在所有端点中选择
+    pub fn select(&self) -> usize {
+        let now = Instant::now();
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+
+        let any_healthy = states.iter().any(|state| is_healthy(state, now));
+        let candidates = (0..self.endpoints.len())
+            .filter(|&i| !any_healthy || is_healthy(&states[i], now))
+            .collect::<Vec<_>>();
+        self.pick(&mut states, &candidates)
+    }
+
+    // 故障转移：在本次请求尚未尝试过的健康端点中选择，没有时返回 None
+    pub fn select_excluding(&self, tried: &[usize]) -> Option<usize> {
+        let now = Instant::now();
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+
+        let candidates = (0..self.endpoints.len())
+            .filter(|i| !tried.contains(i) && is_healthy(&states[*i], now))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(self.pick(&mut states, &candidates))
+    }
+
+    fn pick(&self, states: &mut [EndpointState], candidates: &[usize]) -> usize {
+        let total = candidates.iter().map(|&i| i64::from(self.endpoints[i].weight)).sum::<i64>();
+        let mut best = candidates[0];
+        for &i in candidates {
+            states[i].current_weight += i64::from(self.endpoints[i].weight);
+            if states[i].current_weight > states[best].current_weight {
+                best = i;
+            }
+        }
+        states[best].current_weight -= total;
+        best
+    }
+
+    pub fn record(&self, index: usize, outcome: EndpointOutcome, latency: Duration) {
+        let mut states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut states[index];
+        state.requests += 1;
+        state.total_latency += latency;
+
+        match outcome {
+            EndpointOutcome::Success => {
+                state.successes += 1;
+                state.consecutive_failures = 0;
+                state.unhealthy_until = None;
+            },
+            EndpointOutcome::RateLimited(retry_after) => {
+                state.rate_limited += 1;
+                if let Some(retry_after) = retry_after {
+                    let until = Instant::now() + retry_after;
+                    state.unhealthy_until = Some(state.unhealthy_until.map_or(until, |current| current.max(until)));
+                }
+            },
+            EndpointOutcome::Rejected => {
+                state.rejected += 1;
+            },
+            EndpointOutcome::Failure => {
+                state.failures += 1;
+                state.consecutive_failures += 1;
+                if state.consecutive_failures >= self.unhealthy_threshold {
+                    state.unhealthy_until = Some(Instant::now() + self.cooldown);
+                    eprintln!("RPC endpoint {} marked unhealthy after {} consecutive failures", self.endpoints[index].url, state.consecutive_failures);
+                }
+            },
+        }
+    }
+
+    pub fn metrics(&self) -> Vec<EndpointMetrics> {
+        let now = Instant::now();
+        let states = self.states.lock().unwrap_or_else(|e| e.into_inner());
+        self.endpoints
+            .iter()
+            .zip(states.iter())
+            .map(|(endpoint, state)| EndpointMetrics {
+                url: endpoint.url.clone(),
+                weight: endpoint.weight,
+                healthy: is_healthy(state, now),
+                requests: state.requests,
+                successes: state.successes,
+                failures: state.failures,
+                rate_limited: state.rate_limited,
+                rejected: state.rejected,
+                consecutive_failures: state.consecutive_failures,
+                average_latency_ms: if state.requests > 0 {
+                    state.total_latency.as_secs_f64() * 1000.0 / state.requests as f64
+                } else {
+                    0.0
+                },
+            })
+            .collect()
+    }
+}
+
+fn is_healthy(state: &EndpointState, now: Instant) -> bool {
+    state.unhealthy_until.is_none_or(|until| until <= now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(weights: Vec<u32>) -> EndpointPool {
+        let config = RpcConfig {
+            urls: (0..weights.len()).map(|i| format!("https://rpc{}.example", i)).collect(),
+            weights,
+            unhealthy_threshold: 2,
+            cooldown_secs: 60,
+            ..RpcConfig::default()
+        };
+        EndpointPool::new(&config)
+    }
+
+    #[test]
+    fn test_weighted_round_robin() {
+        let pool = pool(vec![3, 1]);
+        let selected = (0..8).map(|_| pool.select()).collect::<Vec<_>>();
+        assert_eq!(selected.iter().filter(|&&i| i == 0).count(), 6);
+        assert_eq!(selected.iter().filter(|&&i| i == 1).count(), 2);
+        // 平滑：权重大的端点不会连续占满
+        assert_eq!(&selected[..4], &[0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_failover_to_healthy_endpoint() {
+        let pool = pool(vec![1, 1]);
+        pool.record(0, EndpointOutcome::Failure, Duration::ZERO);
+        pool.record(0, EndpointOutcome::Failure, Duration::ZERO);
+        assert!((0..4).all(|_| pool.select() == 1));
+
+        let metrics = pool.metrics();
+        assert!(!metrics[0].healthy);
+        assert_eq!(metrics[0].failures, 2);
+        assert!(metrics[1].healthy);
+
+        // 成功后恢复健康
+        pool.record(0, EndpointOutcome::Success, Duration::ZERO);
+        assert!(pool.metrics()[0].healthy);
+    }
+
+    #[test]
+    fn test_select_excluding_tried_endpoints() {
+        // 权重 [3, 1] 下平滑轮询大多会再次选中 0，故障转移必须排除已经试过的端点
+        let pool = pool(vec![3, 1]);
+        assert_eq!(pool.select(), 0);
+        pool.record(0, EndpointOutcome::Failure, Duration::ZERO);
+        assert_eq!(pool.select_excluding(&[0]), Some(1));
+        assert_eq!(pool.select_excluding(&[0, 1]), None);
+    }
+
+    #[test]
+    fn test_rate_limited_cooldown() {
+        let cooled = pool(vec![3, 1]);
+        cooled.record(0, EndpointOutcome::RateLimited(Some(Duration::from_secs(30))), Duration::ZERO);
+        assert!(!cooled.metrics()[0].healthy);
+        assert!((0..4).all(|_| cooled.select() == 1));
+        assert_eq!(cooled.select_excluding(&[1]), None);
+
+        // 没有 Retry-After 时只计入指标
+        let limited = pool(vec![3, 1]);
+        limited.record(0, EndpointOutcome::RateLimited(None), Duration::ZERO);
+        let metrics = limited.metrics();
+        assert!(metrics[0].healthy);
+        assert_eq!(metrics[0].rate_limited, 1);
+    }
+
+    #[test]
+    fn test_all_unhealthy_still_selects() {
+        let pool = pool(vec![1]);
+        pool.record(0, EndpointOutcome::Failure, Duration::ZERO);
+        pool.record(0, EndpointOutcome::Failure, Duration::ZERO);
+        assert_eq!(pool.select(), 0);
+    }
+}
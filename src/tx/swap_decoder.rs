@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use anchor_lang::{event::EVENT_IX_TAG_LE, solana_program::hash::hash};
+use solana_sdk::{bs58, pubkey::Pubkey};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, UiInstruction, UiParsedInstruction, UiTransactionStatusMeta,
+};
+
+use crate::handle::base_token::WSOL;
+use crate::tx::decoders::{jupiter, meteora, orca, pump_fun, raydium};
+use crate::tx::inner_tx::SwapInstruction;
+
+// 按程序 id 分发的 swap 解码器，每个 AMM 程序一个实现
+pub trait SwapDecoder: Send + Sync {
+    fn program_id(&self) -> Pubkey;
+
+    fn name(&self) -> &'static str;
+
+    // 解码一条发往该程序的指令，不是 swap 或无法确定用户的输入输出时返回 None
+    fn decode(&self, ctx: &InstructionContext) -> Option<SwapInstruction>;
+
+    // 存入或取回托管资金的指令（如 DCA 的开启/关闭、限价单的创建/取消），不是交易，也不应被当作转账
+    fn is_escrow(&self, _ctx: &InstructionContext) -> bool {
+        false
+    }
+}
+
+// 所有内置的非 Jupiter 解码器
+pub fn default_decoders() -> Vec<Box<dyn SwapDecoder>> {
+    vec![
+        Box::new(raydium::RaydiumAmmV4),
+        Box::new(raydium::RaydiumCpmm),
+        Box::new(raydium::RaydiumClmm),
+        Box::new(orca::Whirlpool),
+        Box::new(meteora::MeteoraDlmm),
+        Box::new(meteora::MeteoraPools),
+        Box::new(pump_fun::PumpFun),
+        Box::new(jupiter::JupiterDca),
+        Box::new(jupiter::JupiterLimitOrder::v1()),
+        Box::new(jupiter::JupiterLimitOrder::v2()),
+    ]
+}
+
+// Anchor 指令的 discriminator：sha256("global:<name>")[..8]
+pub fn anchor_discriminator(name: &str) -> [u8; 8] {
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash(format!("global:{}", name).as_bytes()).to_bytes()[..8]);
+    discriminator
+}
+
+// 指令数据是否以给定 Anchor 指令之一的 discriminator 开头
+pub fn is_anchor_instruction(data: &[u8], names: &[&str]) -> bool {
+    data.len() >= 8 && names.iter().any(|name| data[..8] == anchor_discriminator(name))
+}
+
+// 代币账户 -> (mint, owner)，由 pre/post token balances 与账户列表得到
+#[derive(Debug, Clone, Default)]
+pub struct TokenAccounts {
+    accounts: HashMap<Pubkey, (Pubkey, Pubkey)>,
+}
+
+impl TokenAccounts {
+    pub fn new(account_keys: &[Pubkey], meta: &UiTransactionStatusMeta) -> Self {
+        let mut accounts = HashMap::new();
+        for balances in [meta.pre_token_balances.as_ref(), meta.post_token_balances.as_ref()] {
+            let OptionSerializer::Some(balances) = balances else {
+                continue;
+            };
+            for balance in balances {
+                let (Some(account), Ok(mint), OptionSerializer::Some(owner)) = (
+                    account_keys.get(balance.account_index as usize),
+                    balance.mint.parse::<Pubkey>(),
+                    balance.owner.as_ref(),
+                ) else {
+                    continue;
+                };
+                if let Ok(owner) = owner.parse::<Pubkey>() {
+                    accounts.insert(*account, (mint, owner));
+                }
+            }
+        }
+        TokenAccounts { accounts }
+    }
+
+    // 本交易中新建并随即关闭的账户（如临时 WSOL 账户）不在 token balances 中，从 initializeAccount 指令补充
+    pub fn add_initialized<'a>(&mut self, instructions: impl IntoIterator<Item = &'a UiInstruction>) {
+        for instruction in instructions {
+            let UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) = instruction else {
+                continue;
+            };
+            if !matches!(ix.program.as_str(), "spl-token" | "spl-token-2022") {
+                continue;
+            }
+            let kind = ix.parsed.get("type").and_then(|kind| kind.as_str()).unwrap_or_default();
+            if !kind.starts_with("initializeAccount") {
+                continue;
+            }
+            let Some(info) = ix.parsed.get("info") else {
+                continue;
+            };
+            let account = |field: &str| info.get(field)?.as_str()?.parse::<Pubkey>().ok();
+            if let (Some(account), Some(mint), Some(owner)) = (account("account"), account("mint"), account("owner")) {
+                self.accounts.entry(account).or_insert((mint, owner));
+            }
+        }
+    }
+
+    pub fn get(&self, account: &Pubkey) -> Option<(Pubkey, Pubkey)> {
+        self.accounts.get(account).copied()
+    }
+
+    // owner 持有的代币账户的 mint（如限价单托管账户）
+    pub fn mint_owned_by(&self, owner: &Pubkey) -> Option<Pubkey> {
+        self.accounts.values().find(|(_, o)| o == owner).map(|(mint, _)| *mint)
+    }
+}
+
+// 一次 SPL Token 或 System 转账，SOL 转账记为 WSOL，owner 即钱包本身
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub source_owner: Option<Pubkey>,
+    pub destination_owner: Option<Pubkey>,
+}
+
+// 发往某个程序的一条指令及其 CPI 产生的子指令
+pub struct InstructionContext<'a> {
+    pub user: &'a Pubkey,
+    pub data: &'a [u8],
+    pub children: &'a [UiInstruction],
+    pub token_accounts: &'a TokenAccounts,
+}
+
+impl InstructionContext<'_> {
+    // program 通过 emit_cpi 发出的指定类型事件（去掉 event tag 与 discriminator 后的数据）
+    pub fn cpi_events(&self, program: Pubkey, discriminator: &[u8; 8]) -> Vec<Vec<u8>> {
+        self.children
+            .iter()
+            .filter(|ix| program_id(ix) == Some(program))
+            .filter_map(raw_data)
+            .filter_map(|data| {
+                let payload = data.strip_prefix(EVENT_IX_TAG_LE)?.strip_prefix(&discriminator[..])?;
+                Some(payload.to_vec())
+            })
+            .collect()
+    }
+
+    pub fn transfers(&self) -> Vec<Transfer> {
+        self.children.iter().filter_map(|ix| transfer(ix, self.token_accounts)).collect()
+    }
+
+    // 按用户视角汇总子指令中的转账：唯一净流出的 mint 为输入，唯一净流入的 mint 为输出
+    pub fn user_swap(&self, amm: Pubkey) -> Option<SwapInstruction> {
+        let mut flows: Vec<(Pubkey, i128)> = vec![];
+        for transfer in self.transfers() {
+            let mut delta = 0i128;
+            if transfer.source_owner == Some(*self.user) {
+                delta -= i128::from(transfer.amount);
+            }
+            if transfer.destination_owner == Some(*self.user) {
+                delta += i128::from(transfer.amount);
+            }
+            match flows.iter_mut().find(|(mint, _)| *mint == transfer.mint) {
+                Some((_, flow)) => *flow += delta,
+                None => flows.push((transfer.mint, delta)),
+            }
+        }
+
+        let mut inputs = flows.iter().filter(|(_, flow)| *flow < 0);
+        let mut outputs = flows.iter().filter(|(_, flow)| *flow > 0);
+        let (Some(input), None, Some(output), None) = (inputs.next(), inputs.next(), outputs.next(), outputs.next()) else {
+            return None;
+        };
+        Some(SwapInstruction {
+            amm,
+            input_mint: input.0,
+            input_amount: u64::try_from(input.1.unsigned_abs()).ok()?,
+            output_mint: output.0,
+            output_amount: u64::try_from(output.1.unsigned_abs()).ok()?,
+        })
+    }
+}
+
+pub fn program_id(instruction: &UiInstruction) -> Option<Pubkey> {
+    match instruction {
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) => ix.program_id.parse().ok(),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => ix.program_id.parse().ok(),
+        UiInstruction::Compiled(_) => None,
+    }
+}
+
+pub fn stack_height(instruction: &UiInstruction) -> Option<u32> {
+    match instruction {
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) => ix.stack_height,
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => ix.stack_height,
+        UiInstruction::Compiled(ix) => ix.stack_height,
+    }
+}
+
+// RPC 节点无法解析的指令以 base58 原始数据提供
+pub fn raw_data(instruction: &UiInstruction) -> Option<Vec<u8>> {
+    match instruction {
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => bs58::decode(&ix.data).into_vec().ok(),
+        _ => None,
+    }
+}
+
+// 解析 spl-token / spl-token-2022 的 transfer、transferChecked、transferCheckedWithFee 以及 system 的 transfer
+// 与 SwapEvent 一致使用转账前的数量：带转账费用的 mint 上普通 transferChecked 同样会被扣费，统一由 SwapItem 按 mint 的费率调整
+fn transfer(instruction: &UiInstruction, token_accounts: &TokenAccounts) -> Option<Transfer> {
+    let UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) = instruction else {
+        return None;
+    };
+    let kind = ix.parsed.get("type")?.as_str()?;
+    let info = ix.parsed.get("info")?;
+    let account = |field: &str| info.get(field)?.as_str()?.parse::<Pubkey>().ok();
+
+    match (ix.program.as_str(), kind) {
+        ("system", "transfer") => Some(Transfer {
+            mint: Pubkey::from_str_const(WSOL),
+            amount: info.get("lamports")?.as_u64()?,
+            source_owner: account("source"),
+            destination_owner: account("destination"),
+        }),
+        ("spl-token" | "spl-token-2022", "transfer" | "transferChecked" | "transferCheckedWithFee") => {
+            let source = token_accounts.get(&account("source")?);
+            let destination = token_accounts.get(&account("destination")?);
+            let mint = match account("mint") {
+                Some(mint) => mint,
+                None => source.or(destination)?.0,
+            };
+            let amount = match kind {
+                "transfer" => info.get("amount")?.as_str()?,
+                _ => info.get("tokenAmount")?.get("amount")?.as_str()?,
+            };
+            Some(Transfer {
+                mint,
+                amount: amount.parse().ok()?,
+                // 转出账户的 authority 即为用户（如本交易中关闭的临时 WSOL 账户）
+                source_owner: source.map(|(_, owner)| owner).or_else(|| account("authority")),
+                destination_owner: destination.map(|(_, owner)| owner),
+            })
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = "J14Cg556roeBSgWFEKNTiSQeydMPRW6FZNB2zDMmSadQ";
+    const POOL: &str = "KMNo3nJsBXfcpJTVhZcXLW7RmTwTt4GVFE7suUBo9sS";
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+    fn token_transfer(source: &Pubkey, destination: &Pubkey, authority: &str, amount: u64) -> UiInstruction {
+        serde_json::from_value(serde_json::json!({
+            "program": "spl-token",
+            "programId": "TokenkegQfeZyiNwAJbNbGCPFdR3aeNhbhQjWR4CZn5",
+            "parsed": {
+                "type": "transfer",
+                "info": {
+                    "source": source.to_string(),
+                    "destination": destination.to_string(),
+                    "authority": authority,
+                    "amount": amount.to_string()
+                }
+            },
+            "stackHeight": 2
+        }))
+        .expect("Failed to build transfer")
+    }
+
+    #[test]
+    fn test_user_swap_from_transfers() {
+        let user = Pubkey::from_str_const(USER);
+        let pool = Pubkey::from_str_const(POOL);
+        let mint = Pubkey::new_unique();
+        let (user_usdc, user_token, pool_usdc, pool_token) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let token_accounts = TokenAccounts {
+            accounts: HashMap::from([
+                (user_usdc, (Pubkey::from_str_const(USDC), user)),
+                (user_token, (mint, user)),
+                (pool_usdc, (Pubkey::from_str_const(USDC), pool)),
+                (pool_token, (mint, pool)),
+            ]),
+        };
+        let children = vec![
+            token_transfer(&user_usdc, &pool_usdc, USER, 100_000_000),
+            token_transfer(&pool_token, &user_token, POOL, 42_000),
+        ];
+        let ctx = InstructionContext { user: &user, data: &[], children: &children, token_accounts: &token_accounts };
+
+        let swap = ctx.user_swap(pool).expect("expected swap");
+        assert_eq!(swap.input_mint, Pubkey::from_str_const(USDC));
+        assert_eq!(swap.input_amount, 100_000_000);
+        assert_eq!(swap.output_mint, mint);
+        assert_eq!(swap.output_amount, 42_000);
+
+        // 其他用户的 swap
+        let other = Pubkey::new_unique();
+        let ctx = InstructionContext { user: &other, data: &[], children: &children, token_accounts: &token_accounts };
+        assert!(ctx.user_swap(pool).is_none());
+    }
+}
@@ -0,0 +1,92 @@
+use solana_sdk::{epoch_schedule::EpochSchedule, pubkey::Pubkey};
+use spl_token_2022::extension::transfer_fee::{TransferFee, TransferFeeConfig};
+use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+use spl_token_2022::state::Mint;
+
+// Token-2022 mint 的 TransferFeeConfig 扩展，经典 SPL Token 或没有该扩展时返回 None
+pub fn transfer_fee_config(owner: &Pubkey, data: &[u8]) -> Option<TransferFeeConfig> {
+    if *owner != spl_token_2022::id() {
+        return None;
+    }
+    let mint = StateWithExtensions::<Mint>::unpack(data).ok()?;
+    mint.get_extension::<TransferFeeConfig>().ok().copied()
+}
+
+// 交易所在 slot 生效的转账费率（mainnet 没有 warmup epoch）
+// mint 只保留最近两次费率，older_transfer_fee 生效之前的 epoch 费率已被覆盖，无法得知，返回 None
+pub fn epoch_transfer_fee(config: &TransferFeeConfig, slot: u64) -> Option<TransferFee> {
+    let epoch = EpochSchedule::without_warmup().get_epoch(slot);
+    (epoch >= u64::from(config.older_transfer_fee.epoch)).then(|| *config.get_epoch_fee(epoch))
+}
+
+// 转出 amount 时接收方实际到账的数量，扣除的部分由 mint 暂扣在接收方账户中
+pub fn received_amount(fee: &TransferFee, amount: u64) -> u64 {
+    amount.saturating_sub(fee.calculate_fee(amount).unwrap_or(0))
+}
+
+// 由交易本身推算实际扣除的转账费用：事件中的到账数量扣除平台费用后，与用户余额实际增加的差额
+// 差额为负或超过到账数量时（事件与余额不一致）返回 None
+pub fn withheld_fee(received: u64, platform_fee: u64, delta: i128) -> Option<u64> {
+    let fee = i128::from(received) - i128::from(platform_fee) - delta;
+    u64::try_from(fee).ok().filter(|fee| *fee <= received)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spl_token_2022::extension::{ExtensionType, StateWithExtensionsMut, BaseStateWithExtensionsMut};
+
+    fn mint_with_fee(older_bps: u16, older_epoch: u64, newer_bps: u16, newer_epoch: u64) -> Vec<u8> {
+        let len = ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferFeeConfig]).unwrap();
+        let mut data = vec![0u8; len];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        let extension = state.init_extension::<TransferFeeConfig>(true).unwrap();
+        extension.older_transfer_fee = TransferFee {
+            epoch: older_epoch.into(),
+            maximum_fee: u64::MAX.into(),
+            transfer_fee_basis_points: older_bps.into(),
+        };
+        extension.newer_transfer_fee = TransferFee {
+            epoch: newer_epoch.into(),
+            maximum_fee: 1_000.into(),
+            transfer_fee_basis_points: newer_bps.into(),
+        };
+        state.base.decimals = 6;
+        state.base.is_initialized = true;
+        state.pack_base();
+        state.init_account_type().unwrap();
+        data
+    }
+
+    #[test]
+    fn test_transfer_fee() {
+        let data = mint_with_fee(100, 0, 200, 800);
+        let config = transfer_fee_config(&spl_token_2022::id(), &data).expect("expected transfer fee config");
+        // 经典 SPL Token 的 mint 不解析扩展
+        assert!(transfer_fee_config(&Pubkey::new_unique(), &data).is_none());
+
+        // epoch 799：1%
+        let fee = epoch_transfer_fee(&config, 799 * 432_000).unwrap();
+        assert_eq!(received_amount(&fee, 1_000_000), 990_000);
+        // epoch 800 之后：2%，最多 1000
+        let fee = epoch_transfer_fee(&config, 800 * 432_000).unwrap();
+        assert_eq!(received_amount(&fee, 10_000), 9_800);
+        assert_eq!(received_amount(&fee, 1_000_000), 999_000);
+    }
+
+    #[test]
+    fn test_overwritten_transfer_fee() {
+        // 费率更新过多次，epoch 500 之前的费率已不在配置中
+        let data = mint_with_fee(100, 500, 200, 800);
+        let config = transfer_fee_config(&spl_token_2022::id(), &data).unwrap();
+        assert!(epoch_transfer_fee(&config, 499 * 432_000).is_none());
+        assert!(epoch_transfer_fee(&config, 500 * 432_000).is_some());
+
+        // 事件到账 1_000_000，其中 5_000 为平台费用，余额实际增加 945_000：转账费用 50_000
+        assert_eq!(withheld_fee(1_000_000, 5_000, 945_000), Some(50_000));
+        assert_eq!(withheld_fee(1_000_000, 0, 1_000_000), Some(0));
+        // 余额增加比事件更多，或余额没有增加以外的异常
+        assert_eq!(withheld_fee(1_000_000, 0, 1_100_000), None);
+        assert_eq!(withheld_fee(1_000_000, 0, -1), None);
+    }
+}